@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::Error;
+use std::io::{Cursor, Error, Read, Seek};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -8,6 +8,15 @@ use alto::{Alto, AltoResult, AsBufferData, Context, Mono, OutputDevice, SampleFr
 use lewton::inside_ogg::OggStreamReader;
 use uuid::Uuid;
 
+/// Embeds `resources/sounds/{name}.ogg` into the binary, evaluates to `&'static [u8]`
+/// suitable for `SoundSystem::play_streaming_bytes`.
+#[macro_export]
+macro_rules! sound {
+    ($name:literal) => {
+        include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/resources/sounds/", $name, ".ogg"))
+    };
+}
+
 pub struct SoundSystem {
     device: OutputDevice,
     context: Context,
@@ -47,7 +56,16 @@ impl SoundSystem {
     pub fn play_streaming_file<P>(&mut self, path: P) -> Result<Uuid, PlaybackError> where P: AsRef<Path> {
         let path = path.as_ref();
         let file = File::open(path)?;
-        let mut reader = OggStreamReader::new(file)?;
+        self.play_streaming(file)
+    }
+
+    /// Plays an Ogg Vorbis file embedded in the binary, see `sound!`.
+    pub fn play_streaming_bytes(&mut self, bytes: &[u8]) -> Result<Uuid, PlaybackError> {
+        self.play_streaming(Cursor::new(bytes))
+    }
+
+    pub fn play_streaming<R>(&mut self, source: R) -> Result<Uuid, PlaybackError> where R: Read + Seek {
+        let mut reader = OggStreamReader::new(source)?;
 
         let mut n = 0;
         let mut len_play = 0.0;
@@ -0,0 +1,165 @@
+//! Pair adjustments of the OpenType GPOS table. Most recent fonts only kern through GPOS,
+//! rusttype reads the legacy `kern` table alone.
+
+use super::tables::{find_table, read_u16, read_u32};
+
+const KERN_FEATURE: &[u8] = b"kern";
+const PAIR_ADJUSTMENT: u16 = 2;
+const EXTENSION: u16 = 9;
+const X_ADVANCE: u16 = 0x0004;
+
+/// Pair adjustment subtables of the lookups the `kern` feature refers to.
+pub struct PairPositioning {
+    /// Copy of the GPOS table, subtable offsets are relative to it.
+    data: Vec<u8>,
+    /// Subtables of every lookup, only the first one covering a glyph applies.
+    lookups: Vec<Vec<usize>>
+}
+
+impl PairPositioning {
+    /// `None` when the font has no GPOS table or doesn't kern through it.
+    pub fn parse(font: &[u8]) -> Option<PairPositioning> {
+        let gpos = find_table(font, b"GPOS")?;
+        let features = read_u16(gpos, 6)? as usize;
+        let lookup_list = read_u16(gpos, 8)? as usize;
+
+        let mut indices = Vec::new();
+        for record in 0 .. read_u16(gpos, features)? as usize {
+            let record = features + 2 + record * 6;
+            if gpos.get(record .. record + 4)? != KERN_FEATURE {
+                continue;
+            }
+            let feature = features + read_u16(gpos, record + 4)? as usize;
+            for i in 0 .. read_u16(gpos, feature + 2)? as usize {
+                indices.push(read_u16(gpos, feature + 4 + i * 2)?);
+            }
+        }
+        indices.sort_unstable();
+        indices.dedup();
+
+        let mut lookups = Vec::new();
+        for index in indices {
+            let lookup = lookup_list + read_u16(gpos, lookup_list + 2 + index as usize * 2)? as usize;
+            let kind = read_u16(gpos, lookup)?;
+            let mut subtables = Vec::new();
+            for i in 0 .. read_u16(gpos, lookup + 4)? as usize {
+                let subtable = lookup + read_u16(gpos, lookup + 6 + i * 2)? as usize;
+                match kind {
+                    PAIR_ADJUSTMENT => subtables.push(subtable),
+                    EXTENSION if read_u16(gpos, subtable + 2)? == PAIR_ADJUSTMENT =>
+                        subtables.push(subtable + read_u32(gpos, subtable + 4)? as usize),
+                    _ => {}
+                }
+            }
+            if !subtables.is_empty() {
+                lookups.push(subtables);
+            }
+        }
+
+        if lookups.is_empty() {
+            return None;
+        }
+        Some(PairPositioning { data: gpos.to_vec(), lookups })
+    }
+
+    /// Advance adjustment of `first` when followed by `second`, in font units.
+    pub fn pair(&self, first: u16, second: u16) -> i32 {
+        self.lookups.iter()
+            .filter_map(|subtables| subtables.iter().find_map(|&subtable| self.subtable_pair(subtable, first, second)))
+            .sum()
+    }
+
+    fn subtable_pair(&self, subtable: usize, first: u16, second: u16) -> Option<i32> {
+        let data = &self.data[..];
+        let coverage_index = coverage(data, subtable + read_u16(data, subtable + 2)? as usize, first)?;
+        let format1 = read_u16(data, subtable + 4)?;
+        let format2 = read_u16(data, subtable + 6)?;
+        let size1 = value_record_size(format1);
+        let size2 = value_record_size(format2);
+
+        let record = match read_u16(data, subtable)? {
+            1 => {
+                let set = subtable + read_u16(data, subtable + 10 + coverage_index * 2)? as usize;
+                let stride = 2 + size1 + size2;
+                let (mut low, mut high) = (0, read_u16(data, set)? as usize);
+                loop {
+                    if low >= high {
+                        return None;
+                    }
+                    let middle = (low + high) / 2;
+                    let pair = set + 2 + middle * stride;
+                    let glyph = read_u16(data, pair)?;
+                    if glyph == second {
+                        break pair + 2;
+                    } else if glyph < second {
+                        low = middle + 1;
+                    } else {
+                        high = middle;
+                    }
+                }
+            }
+            2 => {
+                let class1 = class(data, subtable + read_u16(data, subtable + 8)? as usize, first)?;
+                let class2 = class(data, subtable + read_u16(data, subtable + 10)? as usize, second)?;
+                let class2_count = read_u16(data, subtable + 14)? as usize;
+                subtable + 16 + (class1 * class2_count + class2) * (size1 + size2)
+            }
+            _ => return None
+        };
+
+        if format1 & X_ADVANCE == 0 {
+            return Some(0);
+        }
+        let offset = (format1 & (X_ADVANCE - 1)).count_ones() as usize * 2;
+        Some(read_u16(data, record + offset)? as i16 as i32)
+    }
+}
+
+fn value_record_size(format: u16) -> usize {
+    (format & 0xff).count_ones() as usize * 2
+}
+
+/// Index of the glyph in a coverage table.
+fn coverage(data: &[u8], table: usize, glyph: u16) -> Option<usize> {
+    match read_u16(data, table)? {
+        1 => {
+            let count = read_u16(data, table + 2)? as usize;
+            (0 .. count).find(|&i| read_u16(data, table + 4 + i * 2) == Some(glyph))
+        }
+        2 => {
+            for range in 0 .. read_u16(data, table + 2)? as usize {
+                let range = table + 4 + range * 6;
+                let (start, end) = (read_u16(data, range)?, read_u16(data, range + 2)?);
+                if (start ..= end).contains(&glyph) {
+                    return Some(read_u16(data, range + 4)? as usize + (glyph - start) as usize);
+                }
+            }
+            None
+        }
+        _ => None
+    }
+}
+
+/// Class of the glyph in a class definition table, glyphs that aren't listed are class 0.
+fn class(data: &[u8], table: usize, glyph: u16) -> Option<usize> {
+    match read_u16(data, table)? {
+        1 => {
+            let start = read_u16(data, table + 2)?;
+            let count = read_u16(data, table + 4)?;
+            if glyph < start || glyph - start >= count {
+                return Some(0);
+            }
+            read_u16(data, table + 6 + (glyph - start) as usize * 2).map(|class| class as usize)
+        }
+        2 => {
+            for range in 0 .. read_u16(data, table + 2)? as usize {
+                let range = table + 4 + range * 6;
+                if (read_u16(data, range)? ..= read_u16(data, range + 2)?).contains(&glyph) {
+                    return read_u16(data, range + 4).map(|class| class as usize);
+                }
+            }
+            Some(0)
+        }
+        _ => None
+    }
+}
@@ -0,0 +1,153 @@
+//! Text laid out once and drawn many times. Wrapping, kerning and the vertex buffers of
+//! every line are kept until the text or the parameters shaping it change.
+
+use std::rc::Rc;
+
+use glium::Surface;
+
+use cgmath::Matrix4;
+
+use super::{FontManager, FontParameters, FontTexture, TextAlignHorizontal, TextAlignVertical, TextDisplay, BASELINE};
+
+/// Layouts `FontManager` keeps for `draw_string` and `get_string_bounds`, the least
+/// recently used one is dropped past this.
+const LAYOUT_CACHE_SIZE: usize = 256;
+
+/// Text wrapped into lines along with their vertex buffers, see `FontManager::layout`.
+pub struct TextLayout {
+    text: String,
+    params: FontParameters,
+    lines: Vec<LayoutLine>,
+    width: f32,
+    height: f32,
+    // last time the cache handed it out
+    used: u64
+}
+
+struct LayoutLine {
+    display: TextDisplay<Rc<FontTexture>>,
+    /// Start of the line and its base line, relative to the point the text is aligned on.
+    x: f32,
+    baseline: f32
+}
+
+impl TextLayout {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn params(&self) -> &FontParameters {
+        &self.params
+    }
+
+    /// Size of the text block in pixels, as given by `FontManager::get_string_bounds`.
+    pub fn size(&self) -> (f32, f32) {
+        (self.width, self.height)
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+}
+
+impl FontParameters {
+    /// Whether text drawn with `other` is laid out the same way. Colors, effects and the scissor
+    /// are only used when drawing.
+    pub fn same_layout(&self, other: &FontParameters) -> bool {
+        self.font == other.font && self.size == other.size && self.width_limit == other.width_limit
+            && self.bold == other.bold && self.italic == other.italic
+            && self.underline == other.underline && self.strikeout == other.strikeout
+            && self.subpixel == other.subpixel && self.sdf == other.sdf
+            && self.align_horizontal == other.align_horizontal && self.align_vertical == other.align_vertical
+    }
+}
+
+impl FontManager {
+    /// Wraps the text to `params.width_limit` and builds the buffers of every line.
+    /// The layout isn't updated when fonts are registered or fallbacks change afterwards.
+    pub fn layout(&mut self, text: &str, params: &FontParameters) -> TextLayout {
+        let em = params.size as f32 / 2.0;
+        let texture = self.texture_for(params);
+        let wrapped = texture.wrap(text, params.width_limit / em);
+
+        let height = texture.block_height(wrapped.len(), params.underline) * em;
+        let top = match params.align_vertical {
+            TextAlignVertical::Top => 0.0,
+            TextAlignVertical::Bottom => -height,
+            TextAlignVertical::Center => -height / 2.0
+        };
+
+        let mut width = 0.0f32;
+        let mut lines = Vec::with_capacity(wrapped.len());
+        for (i, line) in wrapped.iter().enumerate() {
+            let w = texture.text_width(line) * em;
+            let x = match params.align_horizontal {
+                TextAlignHorizontal::Left => 0.0,
+                TextAlignHorizontal::Right => -w,
+                TextAlignHorizontal::Center => -w / 2.0
+            };
+            lines.push(LayoutLine {
+                display: TextDisplay::with_options(&self.system, texture.clone(), line, params.text_options()),
+                x,
+                baseline: top + em * (i as f32 + BASELINE)
+            });
+            width = width.max(w);
+        }
+
+        TextLayout {
+            text: text.to_string(),
+            params: params.clone(),
+            lines,
+            width,
+            height,
+            used: 0
+        }
+    }
+
+    /// Draws a layout aligned on `(x, y)`.
+    pub fn draw_layout<S>(&self, target: &mut S, layout: &TextLayout, x: f32, y: f32, viewport: Matrix4<f32>)
+        where S: Surface {
+
+        for line in &layout.lines {
+            self.draw_display(target, &line.display, x + line.x, y + line.baseline, viewport, &layout.params);
+        }
+    }
+
+    /// Layout from the cache, laid out if there is none for this text and parameters.
+    /// Give it back with `cache_layout`.
+    pub(super) fn cached_layout(&mut self, text: &str, params: &FontParameters) -> TextLayout {
+        self.layout_tick += 1;
+        let cached = self.layouts.get_mut(text).and_then(|layouts| {
+            layouts.iter()
+                .position(|layout| layout.params.same_layout(params))
+                .map(|index| layouts.swap_remove(index))
+        });
+        let mut layout = match cached {
+            Some(mut layout) => {
+                self.layout_ages.remove(&layout.used);
+                layout.params = params.clone();
+                layout
+            }
+            None => self.layout(text, params)
+        };
+        layout.used = self.layout_tick;
+        layout
+    }
+
+    pub(super) fn cache_layout(&mut self, layout: TextLayout) {
+        // every cached layout has an age, ticks are unique
+        if self.layout_ages.len() >= LAYOUT_CACHE_SIZE {
+            if let Some((used, text)) = self.layout_ages.pop_first() {
+                let layouts = self.layouts.get_mut(&text).unwrap();
+                if let Some(index) = layouts.iter().position(|layout| layout.used == used) {
+                    layouts.swap_remove(index);
+                }
+                if layouts.is_empty() {
+                    self.layouts.remove(&text);
+                }
+            }
+        }
+        self.layout_ages.insert(layout.used, layout.text.clone());
+        self.layouts.entry(layout.text.clone()).or_default().push(layout);
+    }
+}
@@ -134,7 +134,8 @@ shall add +1 to the outer bound.*
  */
 
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::default::Default;
 use std::io::Read;
 use std::ops::Deref;
@@ -146,21 +147,63 @@ use glium::{DrawParameters, Surface, backend::{Context, Facade}, Rect as GLRect,
 
 use cgmath::{Matrix4, Vector3};
 
-pub const DEFAULT_FONT: &'static [u8] = include_bytes!("../../resources/fonts/default.ttf");
+use crate::shaders::ShaderManager;
+use crate::textures::RectPacker;
+
+mod kerning;
+mod layout;
+mod rich;
+mod sdf;
+mod tables;
+
+use kerning::PairPositioning;
+pub use layout::TextLayout;
+pub use rich::{parse_markup, RichItem, RichLayout};
+use rich::{break_lines, Item};
+use sdf::{distance_field, SDF_FONT_SIZE, SDF_SPREAD};
+use tables::Decorations;
+
+/// Embeds `resources/fonts/{name}.ttf` into the binary, evaluates to `&'static [u8]`.
+#[macro_export]
+macro_rules! font {
+    ($name:literal) => {
+        include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/resources/fonts/", $name, ".ttf"))
+    };
+}
+
+pub const DEFAULT_FONT: &'static [u8] = font!("default");
+/// Family name `DEFAULT_FONT` is registered under.
+pub const DEFAULT_FONT_NAME: &str = "default";
 pub const DEFAULT_FONT_SIZE: u32 = 40;
 pub const BOLD_FACTOR: f32 = 100.0 / 3.0;
 pub const ITALIC_FACTOR: f32 = 1333.3;
+/// Distance from the top of a line to its base line, in line heights.
+const BASELINE: f32 = 0.777777775;
 
 #[derive(Clone)]
 pub struct FontParameters {
+    /// Family registered in `FontManager`.
+    pub font: String,
     pub size: u32,
-    pub width_limit: usize,
+    /// Maximum width of a line in pixels, longer text is wrapped.
+    pub width_limit: f32,
     pub color: [f32; 4],
     pub bold: bool,
     pub italic: bool,
     pub strikeout: bool,
     pub underline: bool,
     pub scissor: Option<GLRect>,
+    /// Keeps fractional glyph positions instead of snapping them to whole pixels.
+    pub subpixel: bool,
+    /// Draws from a distance field atlas shared by all sizes, which stays sharp when scaled
+    /// and allows `outline` and `glow`.
+    pub sdf: bool,
+    /// Outline color and width in pixels, distance field text only.
+    pub outline: Option<([f32; 4], f32)>,
+    /// Glow color and radius in pixels outside the outline, distance field text only.
+    pub glow: Option<([f32; 4], f32)>,
+    /// Shadow color and offset in pixels, drawn under the text.
+    pub shadow: Option<([f32; 4], [f32; 2])>,
     pub align_horizontal: TextAlignHorizontal,
     pub align_vertical: TextAlignVertical
 }
@@ -168,36 +211,208 @@ pub struct FontParameters {
 impl Default for FontParameters {
     fn default() -> Self {
         FontParameters {
-            size: DEFAULT_FONT_SIZE, width_limit: ::std::usize::MAX,
+            font: DEFAULT_FONT_NAME.into(),
+            size: DEFAULT_FONT_SIZE, width_limit: f32::INFINITY,
             color: [0.0, 0.0, 0.0, 1.0], bold: false, italic: false, underline: false, strikeout: false,
             scissor: None,
+            subpixel: false,
+            sdf: false, outline: None, glow: None, shadow: None,
             align_horizontal: TextAlignHorizontal::Center,
             align_vertical: TextAlignVertical::Top
         }
     }
 }
 
+impl FontParameters {
+    /// Layout options of a line drawn with these parameters.
+    pub fn text_options(&self) -> TextOptions {
+        TextOptions {
+            pixel: if self.subpixel { None } else { Some(2.0 / self.size as f32) },
+            underline: self.underline,
+            strikeout: self.strikeout
+        }
+    }
+
+    /// Outline and glow in distance units, which depend on how much the atlas is scaled.
+    pub fn text_effects(&self) -> TextEffects {
+        if !self.sdf {
+            return TextEffects::default();
+        }
+        // a pixel of the target in atlas pixels, over the distance covered by the field
+        let per_pixel = SDF_FONT_SIZE as f32 / (self.size as f32 / 2.0) / (SDF_SPREAD as f32 * 2.0);
+        let (outline_color, outline_width) = self.outline
+            .map_or(([0.0; 4], 0.0), |(color, width)| (color, (width * per_pixel).min(0.45)));
+        let (glow_color, glow_width) = self.glow
+            .map_or(([0.0; 4], 0.0), |(color, width)| (color, (width * per_pixel).min(0.5 - outline_width)));
+        TextEffects { outline_color, outline_width, glow_color, glow_width }
+    }
+}
+
+/// Face of a font family. Families without a bold or italic face get synthetic styling.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FontStyle {
+    Regular, Bold, Italic, BoldItalic
+}
+
+impl FontStyle {
+    pub fn new(bold: bool, italic: bool) -> FontStyle {
+        match (bold, italic) {
+            (false, false) => FontStyle::Regular,
+            (true, false) => FontStyle::Bold,
+            (false, true) => FontStyle::Italic,
+            (true, true) => FontStyle::BoldItalic
+        }
+    }
+
+    pub fn is_bold(self) -> bool {
+        matches!(self, FontStyle::Bold | FontStyle::BoldItalic)
+    }
+
+    pub fn is_italic(self) -> bool {
+        matches!(self, FontStyle::Italic | FontStyle::BoldItalic)
+    }
+}
+
+/// Parsed font along with the GPOS kerning and line metrics rusttype doesn't read.
+#[derive(Clone)]
+pub struct Face {
+    font: rusttype::Font<'static>,
+    pairs: Option<Rc<PairPositioning>>,
+    decorations: Option<Decorations>
+}
+
+impl Face {
+    pub fn from_vec(bytes: Vec<u8>) -> Result<Face, Error> {
+        let pairs = PairPositioning::parse(&bytes).map(Rc::new);
+        let decorations = Decorations::parse(&bytes);
+        let font = rusttype::Font::try_from_vec(bytes).ok_or(Error::FontError)?;
+        Ok(Face { font, pairs, decorations })
+    }
+
+    /// Kerning between two glyphs, from GPOS if the font kerns through it
+    /// and from the `kern` table otherwise.
+    fn kerning(&self, first: rusttype::GlyphId, second: rusttype::GlyphId) -> f32 {
+        match &self.pairs {
+            // same units as the rest of the metrics, rusttype scales to the line height
+            Some(pairs) => pairs.pair(first.0, second.0) as f32 * self.font.scale_for_pixel_height(1.0),
+            None => self.font.pair_kerning(rusttype::Scale::uniform(1.0), first, second)
+        }
+    }
+}
+
+impl From<rusttype::Font<'static>> for Face {
+    fn from(font: rusttype::Font<'static>) -> Self {
+        Face { font, pairs: None, decorations: None }
+    }
+}
+
 pub struct FontManager {
     display: Display,
     system: TextSystem,
-    textures: HashMap<u32, Rc<FontTexture>>
+    fonts: HashMap<String, HashMap<FontStyle, Face>>,
+    fallbacks: HashMap<String, Vec<String>>,
+    // families asked for but not registered, reported once
+    missing: HashSet<String>,
+    // distance field textures are stored with `SDF_FONT_SIZE` and `true`
+    textures: HashMap<(String, u32, FontStyle, bool), Rc<FontTexture>>,
+    // layouts of `draw_string` and `get_string_bounds` by text, see `cached_layout`
+    layouts: HashMap<String, Vec<TextLayout>>,
+    // text of every cached layout by the tick it was last used at, the oldest first
+    layout_ages: BTreeMap<u64, String>,
+    layout_tick: u64
 }
 
 impl FontManager {
-    pub fn new(display: &Display) -> FontManager {
-        let chars = Self::supported_chars();
-        let mut textures = HashMap::new();
-        textures.insert(DEFAULT_FONT_SIZE, Rc::new(
-            FontTexture::new(display, DEFAULT_FONT, DEFAULT_FONT_SIZE, chars)
-                .expect("Default font texture allocation failed")
-        ));
-        FontManager {
-            display: display.clone(),
-            system: TextSystem::new(display),
-            textures
+    /// Text is drawn with the `font` shader of `shaders` and its `SDF` variant, compiled here.
+    /// If the variant fails to compile its error is put in `errors` and distance field text is
+    /// drawn with the regular program.
+    pub fn new(display: &Display, shaders: Rc<RefCell<ShaderManager>>) -> FontManager {
+        if let Err(e) = shaders.borrow_mut().variant("font", &["SDF"]) {
+            shaders.borrow_mut().errors.insert("font[SDF]".into(), e.report());
         }
+        let mut manager = FontManager {
+            display: display.clone(),
+            system: TextSystem::new(display, shaders),
+            fonts: HashMap::new(),
+            fallbacks: HashMap::new(),
+            missing: HashSet::new(),
+            textures: HashMap::new(),
+            layouts: HashMap::new(),
+            layout_ages: BTreeMap::new(),
+            layout_tick: 0
+        };
+        manager.register_font(DEFAULT_FONT_NAME, FontStyle::Regular, DEFAULT_FONT.to_vec())
+            .expect("Default font is broken");
+        manager.get_or_load_texture(DEFAULT_FONT_NAME, DEFAULT_FONT_SIZE, FontStyle::Regular, false);
+        manager
+    }
+
+    /// Registers a TrueType/OpenType face of a family, replacing the previous face of this style.
+    pub fn register_font<N>(&mut self, name: N, style: FontStyle, bytes: Vec<u8>) -> Result<(), Error>
+        where N: Into<String> {
+
+        let name = name.into();
+        let font = Face::from_vec(bytes)?;
+        let fallbacks = &self.fallbacks;
+        // styles the family had no face for may have been using this one
+        self.textures.retain(|(family, ..), _| {
+            let uses_font = fallbacks.get(family).map_or(false, |fallbacks| fallbacks.contains(&name));
+            !uses_font && *family != name
+        });
+        self.fonts.entry(name).or_default().insert(style, font);
+        self.fonts_changed();
+        Ok(())
+    }
+
+    pub fn load_font<N, P>(&mut self, name: N, style: FontStyle, path: P) -> Result<(), Error>
+        where N: Into<String>, P: AsRef<std::path::Path> {
+
+        let bytes = std::fs::read(path).map_err(Error::Io)?;
+        self.register_font(name, style, bytes)
+    }
+
+    /// Families searched in order for characters the font doesn't have, e.g. a CJK font
+    /// after a latin one. Fallbacks use their own face of the same style.
+    pub fn set_fallbacks<N>(&mut self, name: N, fallbacks: Vec<String>) where N: Into<String> {
+        let name = name.into();
+        self.textures.retain(|(family, ..), _| *family != name);
+        self.fallbacks.insert(name, fallbacks);
+        self.fonts_changed();
     }
 
+    pub fn add_fallback<N, F>(&mut self, name: N, fallback: F) where N: Into<String>, F: Into<String> {
+        let name = name.into();
+        self.textures.retain(|(family, ..), _| *family != name);
+        self.fallbacks.entry(name).or_default().push(fallback.into());
+        self.fonts_changed();
+    }
+
+    // cached layouts were made with the previous fonts
+    fn fonts_changed(&mut self) {
+        self.layouts.clear();
+        self.layout_ages.clear();
+    }
+
+    pub fn has_font<N>(&self, name: N) -> bool where N: AsRef<str> {
+        self.fonts.contains_key(name.as_ref())
+    }
+
+    /// Registered face closest to the requested style, then the regular face and lastly
+    /// whichever face the family has. The family must be registered.
+    fn resolve_style(&self, name: &str, style: FontStyle) -> FontStyle {
+        let faces = &self.fonts[name];
+        let candidates = match style {
+            FontStyle::BoldItalic => vec![FontStyle::BoldItalic, FontStyle::Bold, FontStyle::Italic],
+            other => vec![other]
+        };
+        let any = [FontStyle::Regular, FontStyle::Bold, FontStyle::Italic, FontStyle::BoldItalic];
+        candidates.into_iter()
+            .chain(any)
+            .find(|style| faces.contains_key(style))
+            .expect("Font families have at least one face")
+    }
+
+    /// Characters rasterized up front for every font texture, others are rasterized on first use.
     pub fn supported_chars() -> impl Iterator<Item=char> {
         vec!['•' as u32].into_iter()
             .chain(0 .. 0x7f+1)
@@ -206,77 +421,151 @@ impl FontManager {
             .filter_map(std::char::from_u32)
     }
 
-    fn get_or_load_texture(&mut self, size: u32, chars: impl Iterator<Item=char>) -> Rc<FontTexture> {
-        if !self.textures.contains_key(&size) {
-            self.textures.insert(size, Rc::new(
-                FontTexture::new(&self.display, DEFAULT_FONT, size, chars)
-                    .expect("Font texture allocation failed")
+    /// Texture of the face closest to `style`, emboldened when bold is asked for and
+    /// the family has no bold face. Distance field textures ignore `size`.
+    /// Families that aren't registered are drawn with the default font.
+    fn get_or_load_texture(&mut self, name: &str, size: u32, style: FontStyle, sdf: bool) -> Rc<FontTexture> {
+        let name = if self.fonts.contains_key(name) {
+            name
+        } else {
+            if self.missing.insert(name.to_string()) {
+                eprintln!("Font is missing: {}, using the default font", name);
+            }
+            DEFAULT_FONT_NAME
+        };
+        let resolved = self.resolve_style(name, style);
+        let synthetic_bold = style.is_bold() && !resolved.is_bold();
+        let size = if sdf { SDF_FONT_SIZE } else { size };
+        let key = (name.to_string(), size, FontStyle::new(style.is_bold(), resolved.is_italic()), sdf);
+        if !self.textures.contains_key(&key) {
+            let style = resolved;
+            let mut fonts = vec![self.fonts[name][&style].clone()];
+            for fallback in self.fallbacks.get(name).into_iter().flatten() {
+                let face = self.fonts.get(fallback)
+                    .and_then(|faces| faces.get(&self.resolve_style(fallback, style)));
+                if let Some(face) = face {
+                    fonts.push(face.clone());
+                }
+            }
+            self.textures.insert(key.clone(), Rc::new(
+                FontTexture::with_style(&self.display, fonts, size, GlyphOptions { synthetic_bold, sdf }, Self::supported_chars())
+                    .expect("Font families have at least one face")
             ));
         }
-        self.textures.get(&size).cloned().unwrap()
+        self.textures.get(&key).cloned().unwrap()
+    }
+
+    fn texture_for(&mut self, params: &FontParameters) -> Rc<FontTexture> {
+        self.get_or_load_texture(&params.font, params.size, FontStyle::new(params.bold, params.italic), params.sdf)
     }
 
-    pub fn draw_string<S, T>(&mut self, target: &mut S, text: T, x: f32, y: f32, mut viewport: Matrix4<f32>,
+    pub fn draw_string<S, T>(&mut self, target: &mut S, text: T, x: f32, y: f32, viewport: Matrix4<f32>,
                              params: &FontParameters)
         where S: Surface, T: AsRef<str> {
 
+        let layout = self.cached_layout(text.as_ref(), params);
+        self.draw_layout(target, &layout, x, y, viewport);
+        self.cache_layout(layout);
+    }
+
+    /// Draws a single line as is, starting at `x` with its base line at `baseline`.
+    /// Wrapping and alignment are left to the caller.
+    pub fn draw_line<S>(&mut self, target: &mut S, text: &str, x: f32, baseline: f32, viewport: Matrix4<f32>,
+                        params: &FontParameters)
+        where S: Surface {
+
+        let params = FontParameters {
+            width_limit: f32::INFINITY,
+            align_horizontal: TextAlignHorizontal::Left,
+            align_vertical: TextAlignVertical::Top,
+            ..params.clone()
+        };
+        let em = params.size as f32 / 2.0;
+        let layout = self.cached_layout(text, &params);
+        self.draw_layout(target, &layout, x, baseline - em * BASELINE, viewport);
+        self.cache_layout(layout);
+    }
+
+    /// Draws a line of a layout, starting at `x` with its base line at `baseline`.
+    fn draw_display<S>(&self, target: &mut S, text: &TextDisplay<Rc<FontTexture>>, x: f32, baseline: f32,
+                       mut viewport: Matrix4<f32>, params: &FontParameters)
+        where S: Surface {
+
         let color = &params.color;
-        let text = text.as_ref();
+        let style = self.resolve_style(&params.font, FontStyle::new(params.bold, params.italic));
+        let em = params.size as f32 / 2.0;
 
-        if params.italic {
+        // slanted unless the family has an italic face
+        if params.italic && !style.is_italic() {
             //mat.x.y = 0.0;
             viewport.y.x = params.size as f32 / ITALIC_FACTOR;
         }
 
-        let mut lines = Vec::new();
-        let mut chars = text.chars().collect::<Vec<char>>();
-        let parts = chars.len() / params.width_limit + 1;
+        let effects = params.text_effects();
 
-        for i in 0..parts {
-            let mut line = String::new();
-            for j in 0..(chars.len().min(params.width_limit)) {
-                line.push(chars.remove(0));
-            }
-            lines.push(line);
-        }
-
-        let texture = self.get_or_load_texture(params.size, Self::supported_chars());
-
-        for (i, text) in lines.into_iter().enumerate() {
-            let (w, h) = self.get_string_bounds(&text, params);
-            let x = match params.align_horizontal {
-                TextAlignHorizontal::Left => x,
-                TextAlignHorizontal::Right => x - w,
-                TextAlignHorizontal::Center => x - w / 2.0
-            };
-            let y = match params.align_vertical {
-                TextAlignVertical::Top => y,
-                TextAlignVertical::Bottom => y - h,
-                TextAlignVertical::Center => y - h / 2.0
-            };
+        // the shadow keeps the outline so that it has the shape of the text
+        if let Some((shadow_color, offset)) = params.shadow {
             let mat = viewport
-                * Matrix4::from_translation(Vector3::new(x, y + params.size as f32 / 2.0 * (i as f32 + 0.777777775), 0.0))
-                * Matrix4::from_scale(params.size as f32 / 2.0);
-
-            let text = TextDisplay::new(&self.system, &*texture, text.as_ref());
-
-            draw(&text, &self.system, target, mat, *color, params.scissor.clone())
+                * Matrix4::from_translation(Vector3::new(x + offset[0], baseline + offset[1], 0.0))
+                * Matrix4::from_scale(em);
+            let effects = TextEffects {
+                outline_color: shadow_color,
+                outline_width: effects.outline_width,
+                ..Default::default()
+            };
+            draw_with_effects(text, &self.system, target, mat, shadow_color, effects, params.scissor.clone())
                 .expect("Text drawing failed");
         }
+
+        let mat = viewport
+            * Matrix4::from_translation(Vector3::new(x, baseline, 0.0))
+            * Matrix4::from_scale(em);
+
+        draw_with_effects(text, &self.system, target, mat, *color, effects, params.scissor.clone())
+            .expect("Text drawing failed");
     }
 
+    /// Size of the text block in pixels, one line per line of text after wrapping to `width_limit`.
+    /// Bold text is wider and an underline may reach below the last line.
     pub fn get_string_bounds(&mut self, text: &str, params: &FontParameters) -> (f32, f32) {
-        let texture = self.get_or_load_texture(params.size, Self::supported_chars());
-        let text = TextDisplay::new(&self.system, &*texture, text);
-        let em = params.size as f32 / 2.0;
-        (text.get_width() * em, text.get_height() * em)
+        let layout = self.cached_layout(text, params);
+        let size = layout.size();
+        self.cache_layout(layout);
+        size
     }
 }
 
-/// Texture which contains the characters of the font.
+/// Glyph cache of a font at one size. Characters are rasterized when they are first used
+/// and packed into atlas pages, a new page is added when the current one is full.
 pub struct FontTexture {
+    context: Rc<Context>,
+    /// The font followed by its fallbacks, tried in order for every character.
+    fonts: Vec<Face>,
+    font_size: u32,
+    page_size: u32,
+    pages: RefCell<Vec<GlyphPage>>,
+    // set once a page fails to allocate, glyphs that don't fit the existing pages are skipped
+    pages_failed: Cell<bool>,
+    character_infos: RefCell<HashMap<char, CharacterInfos>>,
+    tofu: RefCell<Option<CharacterInfos>>,
+    // opaque square the lines of underlined and struck out text are drawn with
+    solid: RefCell<Option<CharacterInfos>>,
+    kerning: RefCell<HashMap<(usize, u16, u16), f32>>,
+    options: GlyphOptions,
+}
+
+/// How the glyphs of a `FontTexture` are rasterized.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct GlyphOptions {
+    /// Glyphs are thickened by `BOLD_FACTOR` for families without a bold face.
+    pub synthetic_bold: bool,
+    /// Glyphs are stored as distance fields, to be drawn with the `SDF` variant of the font shader.
+    pub sdf: bool
+}
+
+struct GlyphPage {
     texture: glium::texture::Texture2d,
-    character_infos: HashMap<char, CharacterInfos>,
+    packer: RectPacker
 }
 
 ///
@@ -284,7 +573,10 @@ pub struct FontTexture {
 pub enum Error {
     /// A glyph for this character is not present in font.
     NoGlyph(char),
-    FontError
+    FontError,
+    Io(std::io::Error),
+    /// A glyph page couldn't be allocated.
+    Texture(glium::texture::TextureCreationError)
 }
 
 /// Object that contains the elements shared by all `TextDisplay` objects.
@@ -292,23 +584,49 @@ pub enum Error {
 /// Required to create a `TextDisplay`.
 pub struct TextSystem {
     context: Rc<Context>,
-    program: glium::Program,
+    shaders: Rc<RefCell<ShaderManager>>
 }
 
 /// Object that will allow you to draw a text.
 pub struct TextDisplay<F> where F: Deref<Target=FontTexture> {
     context: Rc<Context>,
     texture: F,
-    vertex_buffer: Option<glium::VertexBuffer<VertexFormat>>,
-    index_buffer: Option<glium::IndexBuffer<u16>>,
+    // one batch per glyph page used by the text
+    buffers: Vec<(usize, glium::VertexBuffer<VertexFormat>, glium::IndexBuffer<u32>)>,
     total_text_width: f32,
     text_height: f32,
-    is_empty: bool,
+    options: TextOptions,
+}
+
+/// Outline and glow of distance field text. Widths are in distance units: 0.5 is
+/// `SDF_SPREAD` pixels of the atlas away from the outline of the glyphs.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TextEffects {
+    pub outline_color: [f32; 4],
+    pub outline_width: f32,
+    pub glow_color: [f32; 4],
+    pub glow_width: f32
+}
+
+/// Layout options of a `TextDisplay`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TextOptions {
+    /// Size of a pixel of the target in EMs that glyph positions are rounded to,
+    /// `None` for subpixel positioning.
+    pub pixel: Option<f32>,
+    pub underline: bool,
+    pub strikeout: bool
 }
 
 // structure containing informations about a character of a font
 #[derive(Copy, Clone, Debug)]
 struct CharacterInfos {
+    // glyph page the character is on
+    page: usize,
+
+    // index of the font the glyph comes from and the glyph in it, `None` for boxes
+    glyph: Option<(usize, rusttype::GlyphId)>,
+
     // coordinates of the character top-left hand corner on the font's texture
     tex_coords: (f32, f32),
 
@@ -321,30 +639,11 @@ struct CharacterInfos {
     // number of EMs between the bottom of the character and the base line of text
     height_over_line: f32,
 
-    // number of EMs at the left of the character
+    // number of EMs between the pen position and the left of the character
     left_padding: f32,
 
-    // number of EMs at the right of the character
-    right_padding: f32,
-}
-
-struct TextureData {
-    data: Vec<f32>,
-    width: u32,
-    height: u32,
-}
-
-impl<'a> glium::texture::Texture2dDataSource<'a> for &'a TextureData {
-    type Data = f32;
-
-    fn into_raw(self) -> glium::texture::RawImage2d<'a, f32> {
-        glium::texture::RawImage2d {
-            data: Cow::Borrowed(&self.data),
-            width: self.width,
-            height: self.height,
-            format: glium::texture::ClientFormat::F32,
-        }
-    }
+    // number of EMs the pen moves forward after the character
+    advance: f32,
 }
 
 #[derive(Copy, Clone, glium_derive::Vertex)]
@@ -359,11 +658,8 @@ impl FontTexture {
         (0 .. 255).filter_map(::std::char::from_u32).collect()
     }
 
-    /// Creates a new texture representing a font stored in a `FontTexture`.
-    /// This function is very expensive as it needs to rasterize font into a
-    /// texture.  Complexity grows as `font_size**2 * characters_list.len()`.
-    /// **Avoid rasterizing everything at once as it will be slow and end up in
-    /// out of memory abort.**
+    /// Creates a glyph cache for a font and rasterizes `characters_list` right away,
+    /// other characters are added when they are first drawn.
     pub fn new<R, F, I>(facade: &F, font: R, font_size: u32, characters_list: I)
                         -> Result<FontTexture, Error>
         where R: Read, F: Facade, I: IntoIterator<Item=char>
@@ -372,18 +668,372 @@ impl FontTexture {
         // building the freetype face object
         let font: Vec<u8> = font.bytes().map(|c| c.unwrap()).collect();
 
-        let font = ::rusttype::Font::try_from_bytes(&font[..]).ok_or(Error::FontError)?;
+        let font = Face::from_vec(font)?;
 
-        // building the infos
-        let (texture_data, chr_infos) =
-            build_font_image(&font, characters_list.into_iter(), font_size)?;
+        Self::with_fallbacks(facade, vec![font], font_size, characters_list)
+    }
 
-        // we load the texture in the display
-        let texture = glium::texture::Texture2d::new(facade, &texture_data).unwrap();
+    /// Same as `new` with an already parsed font, kerned through its `kern` table only.
+    pub fn from_font<F, I>(facade: &F, font: &rusttype::Font<'static>, font_size: u32, characters_list: I)
+                           -> Result<FontTexture, Error>
+        where F: Facade, I: IntoIterator<Item=char>
+    {
+        Self::with_fallbacks(facade, vec![font.clone().into()], font_size, characters_list)
+    }
+
+    /// Glyph cache taking every character from the first font of `fonts` that has it.
+    /// Characters none of them have are drawn as a box.
+    pub fn with_fallbacks<F, I>(facade: &F, fonts: Vec<Face>, font_size: u32, characters_list: I)
+                                -> Result<FontTexture, Error>
+        where F: Facade, I: IntoIterator<Item=char>
+    {
+        Self::with_style(facade, fonts, font_size, GlyphOptions::default(), characters_list)
+    }
+
+    /// Same as `with_fallbacks` with glyphs emboldened or stored as distance fields.
+    pub fn with_style<F, I>(facade: &F, fonts: Vec<Face>, font_size: u32, options: GlyphOptions, characters_list: I)
+                            -> Result<FontTexture, Error>
+        where F: Facade, I: IntoIterator<Item=char>
+    {
+        if fonts.is_empty() {
+            return Err(Error::FontError);
+        }
+        let texture = FontTexture {
+            context: facade.get_context().clone(),
+            fonts,
+            font_size,
+            page_size: (font_size * 16).next_power_of_two().clamp(256, 2048),
+            pages: RefCell::new(Vec::new()),
+            pages_failed: Cell::new(false),
+            character_infos: RefCell::new(HashMap::new()),
+            tofu: RefCell::new(None),
+            solid: RefCell::new(None),
+            kerning: RefCell::new(HashMap::new()),
+            options
+        };
+        // characters too big for a page are skipped, like when drawing them
+        for character in characters_list {
+            texture.glyph(character);
+        }
+        Ok(texture)
+    }
+
+    /// Whether the font or one of its fallbacks has a glyph for the character,
+    /// other characters are drawn as boxes.
+    pub fn has_glyph(&self, character: char) -> bool {
+        self.font_for(character).is_ok()
+    }
+
+    fn font_for(&self, character: char) -> Result<usize, Error> {
+        self.fonts.iter()
+            .position(|face| face.font.glyph(character).id().0 != 0)
+            .ok_or(Error::NoGlyph(character))
+    }
+
+    /// Kerning in EMs to add between two characters, only glyphs of the same font are kerned.
+    fn kerning(&self, first: &CharacterInfos, second: &CharacterInfos) -> f32 {
+        let (font, first, second) = match (first.glyph, second.glyph) {
+            (Some((font, first)), Some((other, second))) if font == other => (font, first, second),
+            _ => return 0.0
+        };
+        *self.kerning.borrow_mut()
+            .entry((font, first.0, second.0))
+            .or_insert_with(|| self.fonts[font].kerning(first, second))
+    }
+
+    /// Width of a line of text in EMs, with kerning.
+    pub fn text_width(&self, text: &str) -> f32 {
+        self.advances(text).iter().sum()
+    }
+
+    /// How far every character of the text moves the pen in EMs, including its kerning
+    /// with the previous one.
+    pub fn advances(&self, text: &str) -> Vec<f32> {
+        let mut previous: Option<CharacterInfos> = None;
+        text.chars()
+            .map(|character| match self.glyph(character) {
+                Some(infos) => {
+                    let kerning = previous.replace(infos)
+                        .map_or(0.0, |previous| self.kerning(&previous, &infos));
+                    kerning + infos.advance
+                }
+                None => 0.0
+            })
+            .collect()
+    }
+
+    /// Splits text into lines no wider than `width` EMs. Lines end at `\n`, and are broken
+    /// after whitespace, after hyphens or at soft hyphens, which are only drawn at line ends.
+    /// Words that don't fit on a line of their own are broken anywhere.
+    pub fn wrap(&self, text: &str, width: f32) -> Vec<String> {
+        let characters = text.chars().collect::<Vec<_>>();
+        let items = characters.iter().zip(self.advances(text))
+            .map(|(&character, advance)| Item {
+                span: 0,
+                character: Some(character),
+                width: if character == SOFT_HYPHEN { 0.0 } else { advance }
+            })
+            .collect::<Vec<_>>();
+        break_lines(&items, width).into_iter()
+            .map(|(start, end, soft)| {
+                let mut line = characters[start .. end].iter()
+                    .filter(|character| **character != SOFT_HYPHEN)
+                    .collect::<String>();
+                line.truncate(line.trim_end().len());
+                if soft {
+                    line.push('-');
+                }
+                line
+            })
+            .collect()
+    }
+
+    /// Number of atlas pages currently allocated.
+    pub fn page_count(&self) -> usize {
+        self.pages.borrow().len()
+    }
+
+    /// Infos of a character, rasterizing it if needed. `None` if the glyph doesn't fit in a page.
+    fn glyph(&self, character: char) -> Option<CharacterInfos> {
+        if let Some(infos) = self.character_infos.borrow().get(&character) {
+            return Some(*infos);
+        }
+        let infos = self.rasterize(character)?;
+        self.character_infos.borrow_mut().insert(character, infos);
+        Some(infos)
+    }
 
-        Ok(FontTexture {
+    fn rasterize(&self, character: char) -> Option<CharacterInfos> {
+        let index = match self.font_for(character) {
+            Ok(index) => index,
+            // whitespace and control characters stay blank
+            Err(Error::NoGlyph(c)) if c == ' ' || c.is_control() => 0,
+            Err(_) => return self.tofu()
+        };
+        let font = &self.fonts[index].font;
+
+        // glyph size for characters not presented in font.
+        let invalid_character_width = self.font_size / 2;
+        let em_pixels = self.font_size as f32;
+
+        let glyph = font.glyph(character);
+        let id = glyph.id();
+        let scaled_glyph = glyph
+            .scaled(::rusttype::Scale { x: em_pixels, y: em_pixels });
+        let h_metrics = scaled_glyph.h_metrics();
+        let glyph = scaled_glyph
+            .positioned(::rusttype::Point { x: 0.0, y: 0.0 });
+
+        let bb = if character == ' ' {
+            Some(Rect {
+                min: Point { x: 0, y: 0 },
+                max: Point { x: invalid_character_width as i32 / 2, y: 0 }
+            })
+        } else {
+            glyph.pixel_bounding_box()
+        };
+        // if no bounding box - we suppose that its invalid character but want it to be draw as empty quad
+        let bb = bb.unwrap_or(Rect {
+            min: Point { x: 0, y: 0 },
+            max: Point { x: invalid_character_width as i32, y: 0 }
+        });
+        let (mut width, mut rows) = (bb.width() as u32, bb.height() as u32);
+        if width > self.page_size || rows > self.page_size {
+            return None;
+        }
+
+        let mut buffer = vec![0u8; (width * rows) as usize];
+        glyph.draw(|x, y, v| {
+            buffer[(y * width + x) as usize] = (v * 255.0) as u8;
+        });
+
+        // emboldening grows the bitmap on every side and the advance by the added width
+        let (mut spread, mut extra_advance) = (0, 0.0);
+        if self.options.synthetic_bold && rows > 0 {
+            let strength = self.font_size as f32 / BOLD_FACTOR;
+            let (bold, radius) = embolden(&buffer, width, rows, strength);
+            buffer = bold;
+            spread = radius;
+            width += radius * 2;
+            rows += radius * 2;
+            extra_advance = strength * 2.0;
+        }
+        if self.options.sdf && rows > 0 {
+            buffer = distance_field(&buffer, width, rows);
+            spread += SDF_SPREAD;
+            width += SDF_SPREAD * 2;
+            rows += SDF_SPREAD * 2;
+        }
+
+        let (page, position) = self.insert_bitmap(width, rows, buffer)?;
+        let page_size = self.page_size as f32;
+        Some(CharacterInfos {
+            page,
+            glyph: Some((index, id)),
+            tex_coords: (position.0 as f32 / page_size, position.1 as f32 / page_size),
+            tex_size: (width as f32 / page_size, rows as f32 / page_size),
+            size: (width as f32 / em_pixels, rows as f32 / em_pixels),
+            // the bitmap starts at the pixel bounds, not at the exact side bearing
+            left_padding: (bb.min.x - spread as i32) as f32 / em_pixels,
+            advance: (h_metrics.advance_width + extra_advance) / em_pixels,
+            height_over_line: (bb.max.y + spread as i32) as f32 / em_pixels,
+        })
+    }
+
+    /// Opaque square inside the atlas, sampled at its center to draw solid quads.
+    fn solid(&self) -> Option<CharacterInfos> {
+        if let Some(infos) = *self.solid.borrow() {
+            return Some(infos);
+        }
+
+        const SIZE: u32 = 4;
+        let (page, position) = self.insert_bitmap(SIZE, SIZE, vec![255; (SIZE * SIZE) as usize])?;
+        let page_size = self.page_size as f32;
+        let infos = CharacterInfos {
+            page,
+            glyph: None,
+            tex_coords: (position.0 as f32 / page_size, position.1 as f32 / page_size),
+            tex_size: (SIZE as f32 / page_size, SIZE as f32 / page_size),
+            size: (0.0, 0.0),
+            left_padding: 0.0,
+            advance: 0.0,
+            height_over_line: 0.0,
+        };
+        *self.solid.borrow_mut() = Some(infos);
+        Some(infos)
+    }
+
+    /// Top and bottom of the underline and of the strikeout, in EMs below the base line.
+    /// Fonts that don't specify them get usual proportions.
+    fn decorations(&self) -> [(f32, f32); 2] {
+        let face = &self.fonts[0];
+        let lines = match face.decorations {
+            Some(decorations) => {
+                let scale = face.font.scale_for_pixel_height(1.0);
+                [
+                    (decorations.underline_position, decorations.underline_thickness),
+                    (decorations.strikeout_position, decorations.strikeout_thickness)
+                ].map(|(position, thickness)| (-position as f32 * scale, thickness as f32 * scale))
+            }
+            None => [(0.08, 0.05), (-0.25, 0.05)]
+        };
+        // at least a pixel of the atlas thick, and thicker for bold text
+        let pixel = 1.0 / self.font_size as f32;
+        let bold = if self.options.synthetic_bold { 1.5 } else { 1.0 };
+        lines.map(|(top, thickness)| (top, top + (thickness * bold).max(pixel)))
+    }
+
+    /// Height in lines of a block of `lines` lines, an underline may reach below the last one.
+    fn block_height(&self, lines: usize, underline: bool) -> f32 {
+        let height = lines as f32;
+        if !underline || lines == 0 {
+            return height;
+        }
+        let [(_, underline_bottom), _] = self.decorations();
+        height.max(lines as f32 - 1.0 + BASELINE + underline_bottom)
+    }
+
+    /// Outlined box standing on the base line, shared by all characters without a glyph.
+    fn tofu(&self) -> Option<CharacterInfos> {
+        if let Some(infos) = *self.tofu.borrow() {
+            return Some(infos);
+        }
+
+        let em_pixels = self.font_size as f32;
+        let (mut width, mut rows) = ((em_pixels * 0.5) as u32 + 2, (em_pixels * 0.7) as u32 + 2);
+        let advance = width as f32 / em_pixels + 0.1;
+        let thickness = (self.font_size / 16).max(1);
+        let mut buffer = vec![0u8; (width * rows) as usize];
+        for y in 0 .. rows {
+            for x in 0 .. width {
+                if x < thickness || y < thickness || x >= width - thickness || y >= rows - thickness {
+                    buffer[(y * width + x) as usize] = 255;
+                }
+            }
+        }
+        let mut spread = 0.0;
+        if self.options.sdf {
+            buffer = distance_field(&buffer, width, rows);
+            spread = SDF_SPREAD as f32 / em_pixels;
+            width += SDF_SPREAD * 2;
+            rows += SDF_SPREAD * 2;
+        }
+
+        let (page, position) = self.insert_bitmap(width, rows, buffer)?;
+        let page_size = self.page_size as f32;
+        let infos = CharacterInfos {
+            page,
+            glyph: None,
+            tex_coords: (position.0 as f32 / page_size, position.1 as f32 / page_size),
+            tex_size: (width as f32 / page_size, rows as f32 / page_size),
+            size: (width as f32 / em_pixels, rows as f32 / em_pixels),
+            left_padding: 0.05 - spread,
+            advance,
+            height_over_line: spread,
+        };
+        *self.tofu.borrow_mut() = Some(infos);
+        Some(infos)
+    }
+
+    /// Packs a single channel bitmap into the current page, or a new one when it's full.
+    /// `None` if it wouldn't fit in an empty page either or no page can be allocated.
+    fn insert_bitmap(&self, width: u32, rows: u32, buffer: Vec<u8>) -> Option<(usize, (u32, u32))> {
+        let mut pages = self.pages.borrow_mut();
+        let position = match pages.last_mut().and_then(|page| page.packer.pack(width, rows)) {
+            Some(position) => position,
+            None => {
+                if self.pages_failed.get() {
+                    return None;
+                }
+                let mut packer = self.create_packer();
+                let position = packer.pack(width, rows)?;
+                match self.create_page(packer) {
+                    Ok(page) => pages.push(page),
+                    Err(e) => {
+                        eprintln!("Glyph page allocation failed: {:?}", e);
+                        self.pages_failed.set(true);
+                        return None;
+                    }
+                }
+                position
+            }
+        };
+        let page = pages.len() - 1;
+
+        if width > 0 && rows > 0 {
+            pages[page].texture.write(
+                GLRect { left: position.0, bottom: position.1, width, height: rows },
+                glium::texture::RawImage2d {
+                    data: Cow::Owned(buffer),
+                    width,
+                    height: rows,
+                    format: glium::texture::ClientFormat::U8
+                }
+            );
+        }
+        Some((page, position))
+    }
+
+    fn create_packer(&self) -> RectPacker {
+        // a margin around each character to prevent artifacts
+        const MARGIN: u32 = 2;
+
+        RectPacker::new(self.page_size, self.page_size, MARGIN)
+    }
+
+    fn create_page(&self, packer: RectPacker) -> Result<GlyphPage, Error> {
+        let size = self.page_size;
+        // the texture starts uninitialized, it's cleared so that sampling around glyphs is blank
+        let texture = glium::texture::Texture2d::new(&self.context, glium::texture::RawImage2d {
+            data: Cow::Owned(vec![0u8; (size * size) as usize]),
+            width: size,
+            height: size,
+            format: glium::texture::ClientFormat::U8
+        }).map_err(Error::Texture)?;
+
+        Ok(GlyphPage {
             texture,
-            character_infos: chr_infos,
+            packer
         })
     }
 }
@@ -396,30 +1046,39 @@ impl FontTexture {
 
 impl TextSystem {
     /// Builds a new text system that must be used to build `TextDisplay` objects.
-    pub fn new<F>(facade: &F) -> TextSystem where F: Facade {
+    /// Text is drawn with the `font` shader of `shaders`, distance field text with its `SDF` variant.
+    pub fn new<F>(facade: &F, shaders: Rc<RefCell<ShaderManager>>) -> TextSystem where F: Facade {
         TextSystem {
             context: facade.get_context().clone(),
-            program: glium::program!(facade,
-                120 => {
-                    vertex: include_str!("../../resources/shaders/font.vsh"),
-                    fragment: include_str!("../../resources/shaders/font.fsh")
-                }
-            ).unwrap()
+            shaders
         }
     }
+
+    /// Looked up on every draw so reloaded shaders apply, distance field text falls back to
+    /// the regular program while its variant fails to compile.
+    fn program(&self, sdf: bool) -> Rc<Box<glium::Program>> {
+        let shaders = self.shaders.borrow();
+        let variant = if sdf { shaders.try_variant("font", &["SDF"]) } else { None };
+        variant.unwrap_or_else(|| shaders.font())
+    }
 }
 
 impl<F> TextDisplay<F> where F: Deref<Target=FontTexture> {
-    /// Builds a new text display that allows you to draw text.
+    /// Builds a new text display that allows you to draw text, glyphs are positioned
+    /// with subpixel precision.
     pub fn new(system: &TextSystem, texture: F, text: &str) -> TextDisplay<F> {
+        Self::with_options(system, texture, text, TextOptions::default())
+    }
+
+    /// Same as `new` with snapping and decorations from `options`.
+    pub fn with_options(system: &TextSystem, texture: F, text: &str, options: TextOptions) -> TextDisplay<F> {
         let mut text_display = TextDisplay {
             context: system.context.clone(),
             texture,
-            vertex_buffer: None,
-            index_buffer: None,
+            buffers: Vec::new(),
             total_text_width: 0.0,
             text_height: 0.0,
-            is_empty: true,
+            options,
         };
 
         text_display.set_text(text);
@@ -439,97 +1098,106 @@ impl<F> TextDisplay<F> where F: Deref<Target=FontTexture> {
 
     /// Modifies the text on this display.
     pub fn set_text(&mut self, text: &str) {
-        self.is_empty = true;
         self.total_text_width = 0.0;
-        self.vertex_buffer = None;
-        self.index_buffer = None;
+        self.buffers.clear();
 
         // returning if no text
         if text.is_empty() {
             return;
         }
 
-        // these arrays will contain the vertex buffer and index buffer data
-        let mut vertex_buffer_data = Vec::with_capacity(text.len() * 4 * 4);
-        let mut index_buffer_data = Vec::with_capacity(text.len() * 6);
+        // vertices and indices of every glyph page
+        let mut batches: Vec<(usize, Vec<VertexFormat>, Vec<u32>)> = Vec::new();
+
+        let mut previous: Option<CharacterInfos> = None;
 
         // iterating over the characters of the string
         for character in text.chars() {
-            let infos = match self.texture.character_infos.get(&character) {
+            let infos = match self.texture.glyph(character) {
                 Some(infos) => infos,
                 None => continue,
             };
 
-            self.is_empty = false;
-
-            // adding the quad in the index buffer
-            {
-                let first_vertex_offset = vertex_buffer_data.len() as u16;
-                index_buffer_data.push(first_vertex_offset);
-                index_buffer_data.push(first_vertex_offset + 1);
-                index_buffer_data.push(first_vertex_offset + 2);
-                index_buffer_data.push(first_vertex_offset + 2);
-                index_buffer_data.push(first_vertex_offset + 1);
-                index_buffer_data.push(first_vertex_offset + 3);
+            if let Some(previous) = previous.replace(infos) {
+                self.total_text_width += self.texture.kerning(&previous, &infos);
             }
 
-            //
-            self.total_text_width += infos.left_padding;
-
             // calculating coords
-            let left_coord = self.total_text_width;
+            let pen = match self.options.pixel {
+                Some(pixel) => (self.total_text_width / pixel).round() * pixel,
+                None => self.total_text_width
+            };
+            let left_coord = pen + infos.left_padding;
             let right_coord = left_coord + infos.size.0;
             let top_coord = infos.height_over_line - infos.size.1;
             let bottom_coord = infos.height_over_line;
 
-            // top-left vertex
-            vertex_buffer_data.push(VertexFormat {
-                pos: [left_coord, top_coord],
-                texture_uv: [infos.tex_coords.0, infos.tex_coords.1],
-            });
-
-            // top-right vertex
-            vertex_buffer_data.push(VertexFormat {
-                pos: [right_coord, top_coord],
-                texture_uv: [infos.tex_coords.0 + infos.tex_size.0, infos.tex_coords.1],
-            });
-
-            // bottom-left vertex
-            vertex_buffer_data.push(VertexFormat {
-                pos: [left_coord, bottom_coord],
-                texture_uv: [infos.tex_coords.0, infos.tex_coords.1 + infos.tex_size.1],
-            });
-
-            // bottom-right vertex
-            vertex_buffer_data.push(VertexFormat {
-                pos: [right_coord, bottom_coord],
-                texture_uv: [
-                    infos.tex_coords.0 + infos.tex_size.0,
-                    infos.tex_coords.1 + infos.tex_size.1
-                ],
-            });
+            push_quad(&mut batches, infos.page, [left_coord, top_coord, right_coord, bottom_coord], [
+                infos.tex_coords.0,
+                infos.tex_coords.1,
+                infos.tex_coords.0 + infos.tex_size.0,
+                infos.tex_coords.1 + infos.tex_size.1
+            ]);
 
             // going to next char
-            self.total_text_width = right_coord + infos.right_padding;
+            self.total_text_width += infos.advance;
 
             if top_coord > self.text_height {
                 self.text_height = top_coord;
             }
         }
 
-        if !vertex_buffer_data.len() != 0 {
-            // building the vertex buffer
-            self.vertex_buffer = Some(glium::VertexBuffer::new(&self.context,
-                                                               &vertex_buffer_data).unwrap());
+        // underline and strikeout across the whole line, sampling the middle of a solid square
+        let [underline, strikeout] = self.texture.decorations();
+        let lines = [(self.options.underline, underline), (self.options.strikeout, strikeout)];
+        for (_, (top, bottom)) in lines.into_iter().filter(|(enabled, _)| *enabled) {
+            if let Some(solid) = self.texture.solid() {
+                let u = solid.tex_coords.0 + solid.tex_size.0 / 2.0;
+                let v = solid.tex_coords.1 + solid.tex_size.1 / 2.0;
+                push_quad(&mut batches, solid.page, [0.0, top, self.total_text_width, bottom], [u, v, u, v]);
+            }
+        }
 
-            // building the index buffer
-            self.index_buffer = Some(glium::IndexBuffer::new(&self.context,
-                                                             glium::index::PrimitiveType::TrianglesList,
-                                                             &index_buffer_data).unwrap());
+        for (page, vertex_buffer_data, index_buffer_data) in batches {
+            let vertex_buffer = glium::VertexBuffer::new(&self.context, &vertex_buffer_data).unwrap();
+            let index_buffer = glium::IndexBuffer::new(&self.context,
+                                                       glium::index::PrimitiveType::TrianglesList,
+                                                       &index_buffer_data).unwrap();
+            self.buffers.push((page, vertex_buffer, index_buffer));
         }
     }
 }
 
+/// Adds a quad to the batch of a glyph page, `bounds` and `uv` are left, top, right and bottom.
+fn push_quad(batches: &mut Vec<(usize, Vec<VertexFormat>, Vec<u32>)>, page: usize, bounds: [f32; 4], uv: [f32; 4]) {
+    let batch = match batches.iter().position(|(batch_page, ..)| *batch_page == page) {
+        Some(index) => index,
+        None => {
+            batches.push((page, Vec::new(), Vec::new()));
+            batches.len() - 1
+        }
+    };
+    let (_, vertex_buffer_data, index_buffer_data) = &mut batches[batch];
+
+    // adding the quad in the index buffer
+    let first_vertex_offset = vertex_buffer_data.len() as u32;
+    index_buffer_data.push(first_vertex_offset);
+    index_buffer_data.push(first_vertex_offset + 1);
+    index_buffer_data.push(first_vertex_offset + 2);
+    index_buffer_data.push(first_vertex_offset + 2);
+    index_buffer_data.push(first_vertex_offset + 1);
+    index_buffer_data.push(first_vertex_offset + 3);
+
+    let [left, top, right, bottom] = bounds;
+    let [u_left, v_top, u_right, v_bottom] = uv;
+
+    // top-left, top-right, bottom-left and bottom-right vertices
+    vertex_buffer_data.push(VertexFormat { pos: [left, top], texture_uv: [u_left, v_top] });
+    vertex_buffer_data.push(VertexFormat { pos: [right, top], texture_uv: [u_right, v_top] });
+    vertex_buffer_data.push(VertexFormat { pos: [left, bottom], texture_uv: [u_left, v_bottom] });
+    vertex_buffer_data.push(VertexFormat { pos: [right, bottom], texture_uv: [u_right, v_bottom] });
+}
+
 /// Draws linear-filtered text.
 ///
 /// ## About the matrix
@@ -550,6 +1218,23 @@ pub fn draw<F, S: ?Sized, M>(
     where S: glium::Surface,
           M: Into<[[f32; 4]; 4]>,
           F: Deref<Target=FontTexture>
+{
+    draw_with_effects(text, system, target, matrix, color, TextEffects::default(), scissor)
+}
+
+/// Same as `draw` with an outline and a glow, which only distance field text supports.
+pub fn draw_with_effects<F, S: ?Sized, M>(
+    text: &TextDisplay<F>,
+    system: &TextSystem,
+    target: &mut S,
+    matrix: M,
+    color: [f32; 4],
+    effects: TextEffects,
+    scissor: Option<GLRect>
+) -> Result<(), glium::DrawError>
+    where S: glium::Surface,
+          M: Into<[[f32; 4]; 4]>,
+          F: Deref<Target=FontTexture>
 {
     let behavior = glium::uniforms::SamplerBehavior {
         magnify_filter: glium::uniforms::MagnifySamplerFilter::Linear,
@@ -578,7 +1263,7 @@ pub fn draw<F, S: ?Sized, M>(
             .. Default::default()
         }
     };
-    draw_with_params(text, system, target, matrix, color, behavior, &params)
+    draw_with_params(text, system, target, matrix, color, effects, behavior, &params)
 }
 
 /// More advanced variant of `draw` which also takes sampler behavior and draw
@@ -589,6 +1274,7 @@ pub fn draw_with_params<F, S: ?Sized, M>(
     target: &mut S,
     matrix: M,
     color: [f32; 4],
+    effects: TextEffects,
     sampler_behavior: glium::uniforms::SamplerBehavior,
     parameters: &DrawParameters
 ) -> Result<(), glium::DrawError>
@@ -597,225 +1283,63 @@ pub fn draw_with_params<F, S: ?Sized, M>(
           F: Deref<Target=FontTexture>
 {
     let matrix = matrix.into();
+    let pages = text.texture.pages.borrow();
+    let program = system.program(text.texture.options.sdf);
+
+    for (page, vertex_buffer, index_buffer) in &text.buffers {
+        // the regular program has no effect uniforms, glium skips them
+        let uniforms = glium::uniform! {
+            mat: matrix,
+            color: color,
+            tex: glium::uniforms::Sampler(&pages[*page].texture, sampler_behavior),
+            outline_color: effects.outline_color,
+            outline_width: effects.outline_width,
+            glow_color: effects.glow_color,
+            glow_width: effects.glow_width
+        };
 
-    let &TextDisplay {
-        ref vertex_buffer,
-        ref index_buffer,
-        ref texture,
-        is_empty,
-        ..
-    } = text;
-
-    // returning if nothing to draw
-    if is_empty || vertex_buffer.is_none() || index_buffer.is_none() {
-        return Ok(());
+        target.draw(vertex_buffer, index_buffer, &**program, &uniforms, &parameters)?;
     }
-
-    let vertex_buffer = vertex_buffer.as_ref().unwrap();
-    let index_buffer = index_buffer.as_ref().unwrap();
-
-    let uniforms = glium::uniform! {
-        mat: matrix,
-        color: color,
-        tex: glium::uniforms::Sampler(&texture.texture, sampler_behavior)
-    };
-
-    target.draw(vertex_buffer, index_buffer, &system.program, &uniforms, &parameters)
+    Ok(())
 }
 
-fn build_font_image<I>(font: &rusttype::Font, characters_list: I, font_size: u32)
-                       -> Result<(TextureData, HashMap<char, CharacterInfos>), Error>
-    where I: Iterator<Item=char> {
-
-    use std::iter;
-
-    // a margin around each character to prevent artifacts
-    const MARGIN: u32 = 2;
-
-    // glyph size for characters not presented in font.
-    let invalid_character_width = font_size / 2;
-
-    let size_estimation = characters_list.size_hint().1.unwrap_or(0);
-
-    // this variable will store the texture data
-    // we set an arbitrary capacity that we think will match what we will need
-    let mut texture_data: Vec<f32> = Vec::with_capacity(
-        size_estimation * font_size as usize * font_size as usize
-    );
-
-    // the width is chosen more or less arbitrarily, because we can store
-    // everything as long as the texture is at least as wide as the widest
-    // character we just try to estimate a width so that width ~= height
-    let texture_width = get_nearest_po2(std::cmp::max(font_size * 2 as u32,
-                                                      ((((size_estimation as u32) * font_size * font_size) as f32).sqrt()) as u32));
-
-    // we store the position of the "cursor" in the destination texture
-    // this cursor points to the top-left pixel of the next character to write on the texture
-    let mut cursor_offset = (0u32, 0u32);
-
-    // number of rows to skip at next carriage return
-    let mut rows_to_skip = 0u32;
-
-    // now looping through the list of characters, filling the texture and returning the informations
-    let em_pixels = font_size as f32;
-    let characters_infos = characters_list.map(|character| {
-        struct Bitmap {
-            rows   : i32,
-            width  : i32,
-            buffer : Vec<u8>
-        }
-        // loading wanted glyph in the font face
-        // hope scale will set the right pixel size
-        let scaled_glyph = font.glyph(character)
-            .scaled(::rusttype::Scale {x : font_size as f32, y : font_size as f32 });
-        let h_metrics = scaled_glyph.h_metrics();
-        let glyph = scaled_glyph
-            .positioned(::rusttype::Point {x : 0.0, y : 0.0 });
-
-        let bb = if character == ' ' {
-            Some(Rect {
-                min: Point {x: 0, y: 0},
-                max: Point {x: invalid_character_width as i32 / 2, y: 0}
-            })
-        } else {
-            glyph.pixel_bounding_box()
-        };
-        // if no bounding box - we suppose that its invalid character but want it to be draw as empty quad
-        let bb = if let Some(bb) = bb {
-            bb
-        } else {
-            Rect {
-                min: Point {x: 0, y: 0},
-                max: Point {x: invalid_character_width as i32, y: 0}
-            }
-        };
-
-        let mut buffer = vec![0; (bb.height() * bb.width()) as usize];
-
-        glyph.draw(|x, y, v| {
-            let x = x;
-            let y = y;
-            buffer[(y * bb.width() as u32 + x) as usize] = (v * 255.0) as u8;
-        });
-        let bitmap : Bitmap = Bitmap {
-            rows   : bb.height(),
-            width  : bb.width(),
-            buffer
-        };
-
-        // adding a left margin before our character to prevent artifacts
-        cursor_offset.0 += MARGIN;
-
-        // carriage return our cursor if we don't have enough room to write the next caracter
-        // we add a margin to prevent artifacts
-        if cursor_offset.0 + (bitmap.width as u32) + MARGIN >= texture_width {
-            assert!(bitmap.width as u32 <= texture_width);       // if this fails, we should increase texture_width
-            cursor_offset.0 = 0;
-            cursor_offset.1 += rows_to_skip;
-            rows_to_skip = 0;
-        }
-
-        // if the texture data buffer has not enough lines, adding some
-        if rows_to_skip < MARGIN + bitmap.rows as u32 {
-            let diff = MARGIN + (bitmap.rows as u32) - rows_to_skip;
-            rows_to_skip = MARGIN + bitmap.rows as u32;
-            texture_data.extend(iter::repeat(0.0).take((diff * texture_width) as usize));
-        }
-
-        // copying the data to the texture
-        let offset_x_before_copy = cursor_offset.0;
-        if bitmap.rows >= 1 {
-            let destination = &mut texture_data[(cursor_offset.0 + cursor_offset.1 * texture_width) as usize ..];
-            let source = &bitmap.buffer;
-            //ylet source = std::slice::from_raw_parts(source, destination.len());
-
-            for y in 0 .. bitmap.rows as u32 {
-                let source = &source[(y * bitmap.width as u32) as usize ..];
-                let destination = &mut destination[(y * texture_width) as usize ..];
-
-                for x in 0 .. bitmap.width {
-                    // the values in source are bytes between 0 and 255, but we want floats between 0 and 1
-                    let val: u8 = source[x as usize];
-                    let val = f32::from(val) / f32::from(std::u8::MAX);
-                    let dest = &mut destination[x as usize];
-                    *dest = val;
+const SOFT_HYPHEN: char = '\u{ad}';
+const NO_BREAK_SPACE: char = '\u{a0}';
+
+/// Spreads the coverage of a glyph bitmap by `strength` pixels in every direction, returns
+/// the bitmap grown by the returned number of pixels on each side.
+fn embolden(buffer: &[u8], width: u32, rows: u32, strength: f32) -> (Vec<u8>, u32) {
+    let radius = (strength + 1.0) as i32;
+    let (new_width, new_rows) = (width as i32 + radius * 2, rows as i32 + radius * 2);
+    let mut output = vec![0u8; (new_width * new_rows) as usize];
+    for y in 0 .. new_rows {
+        for x in 0 .. new_width {
+            let mut value = 0.0f32;
+            for dy in -radius ..= radius {
+                for dx in -radius ..= radius {
+                    let (source_x, source_y) = (x - radius + dx, y - radius + dy);
+                    if source_x < 0 || source_y < 0 || source_x >= width as i32 || source_y >= rows as i32 {
+                        continue;
+                    }
+                    // full coverage within `strength`, fading out over the next pixel
+                    let distance = ((dx * dx + dy * dy) as f32).sqrt();
+                    let weight = (strength + 1.0 - distance).clamp(0.0, 1.0);
+                    let source = buffer[(source_y * width as i32 + source_x) as usize] as f32;
+                    value = value.max(source * weight);
                 }
             }
-
-            cursor_offset.0 += bitmap.width as u32;
-            debug_assert!(cursor_offset.0 <= texture_width);
+            output[(y * new_width + x) as usize] = value as u8;
         }
-
-        // filling infos about that character
-        // tex_size and tex_coords are in pixels for the moment ; they will be divided
-        // by the texture dimensions later
-        Ok((character, CharacterInfos {
-            tex_size: (bitmap.width as f32, bitmap.rows as f32),
-            tex_coords: (offset_x_before_copy as f32, cursor_offset.1 as f32),
-            size: (bitmap.width as f32, bitmap.rows as f32),
-            left_padding: h_metrics.left_side_bearing as f32,
-            right_padding: (h_metrics.advance_width
-                - bitmap.width as f32
-                - h_metrics.left_side_bearing as f32) as f32 / 64.0,
-            height_over_line: bb.max.y as f32,
-        }))
-    }).collect::<Result<Vec<_>, Error>>()?;
-
-    // adding blank lines at the end until the height of the texture is a power of two
-    {
-        let current_height = texture_data.len() as u32 / texture_width;
-        let requested_height = get_nearest_po2(current_height);
-        texture_data.extend(iter::repeat(0.0).take((texture_width * (requested_height - current_height)) as usize));
-    }
-
-    // now our texture is finished
-    // we know its final dimensions, so we can divide all the pixels values into (0,1) range
-    assert!((texture_data.len() as u32 % texture_width) == 0);
-    let texture_height = (texture_data.len() as u32 / texture_width) as f32;
-    let float_texture_width = texture_width as f32;
-    let mut characters_infos = characters_infos.into_iter().map(|mut chr| {
-        chr.1.tex_size.0 /= float_texture_width;
-        chr.1.tex_size.1 /= texture_height;
-        chr.1.tex_coords.0 /= float_texture_width;
-        chr.1.tex_coords.1 /= texture_height;
-        chr.1.size.0 /= em_pixels;
-        chr.1.size.1 /= em_pixels;
-        chr.1.left_padding /= em_pixels;
-        chr.1.right_padding /= em_pixels;
-        chr.1.height_over_line /= em_pixels;
-        chr
-    }).collect::<HashMap<_, _>>();
-
-    // this HashMap will not be used mutably any more and it makes sense to
-    // compact it
-    characters_infos.shrink_to_fit();
-
-    // returning
-    Ok((TextureData {
-        data: texture_data,
-        width: texture_width,
-        height: texture_height as u32,
-    }, characters_infos))
-}
-
-/// Function that will calculate the nearest power of two.
-fn get_nearest_po2(mut x: u32) -> u32 {
-    assert!(x > 0);
-    x -= 1;
-    x = x | (x >> 1);
-    x = x | (x >> 2);
-    x = x | (x >> 4);
-    x = x | (x >> 8);
-    x = x | (x >> 16);
-    x + 1
+    }
+    (output, radius as u32)
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub enum TextAlignHorizontal {
     Left, Right, Center
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub enum TextAlignVertical {
     Top, Bottom, Center
 }
\ No newline at end of file
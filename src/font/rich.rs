@@ -0,0 +1,427 @@
+//! Markup for text mixing styles and icons, e.g.
+//! `"[b]Quest[/b] reward: [color=#fc0]120 [icon=coin][/color]"`.
+//!
+//! | Tag            | Effect                                          |
+//! |----------------|-------------------------------------------------|
+//! | `[b]`, `[i]`   | bold, italic                                    |
+//! | `[u]`, `[s]`   | underline, strikeout                            |
+//! | `[color=#rgb]` | color, also `#rgba`, `#rrggbb` and `#rrggbbaa`  |
+//! | `[size=24]`    | font size, at most `MAX_MARKUP_SIZE`            |
+//! | `[font=name]`  | font family registered in `FontManager`         |
+//! | `[icon=name]`  | region of `TextureManager`, has no closing tag  |
+//!
+//! Tags are closed with `[/b]`, `[/color]`, etc. `[[` is a literal `[`, and tags that
+//! aren't recognized are kept as text. Text in a family that isn't registered is laid out
+//! with the family of the text outside of tags.
+
+use super::{FontManager, FontParameters, TextAlignHorizontal, TextAlignVertical, BASELINE, NO_BREAK_SPACE,
+            SOFT_HYPHEN};
+
+/// Largest size `[size=]` accepts, bigger sizes are clamped.
+pub const MAX_MARKUP_SIZE: u32 = 512;
+
+/// Text or icon of rich text, with the parameters it is drawn with.
+#[derive(Clone)]
+pub enum Span {
+    Text(String, FontParameters),
+    /// Icon sized after the text around it.
+    Icon(String, FontParameters)
+}
+
+/// Splits markup into spans, `params` is the style of the text outside of tags.
+pub fn parse_markup(markup: &str, params: &FontParameters) -> Vec<Span> {
+    let mut spans = Vec::new();
+    // styles of the open tags, the outer style first
+    let mut styles = vec![(String::new(), params.clone())];
+    let mut text = String::new();
+    let mut rest = markup;
+
+    while let Some(start) = rest.find('[') {
+        text.push_str(&rest[.. start]);
+        let after = &rest[start + 1 ..];
+        if let Some(escaped) = after.strip_prefix('[') {
+            text.push('[');
+            rest = escaped;
+            continue;
+        }
+        let end = match after.find(']') {
+            Some(end) => end,
+            None => {
+                text.push('[');
+                rest = after;
+                continue;
+            }
+        };
+        let tag = &after[.. end];
+        rest = &after[end + 1 ..];
+
+        let current = &styles.last().unwrap().1;
+        if let Some(name) = tag.strip_prefix('/') {
+            if let Some(open) = styles.iter().skip(1).rposition(|(open, _)| open == name) {
+                flush(&mut text, current, &mut spans);
+                styles.truncate(open + 1);
+                continue;
+            }
+        } else if let Some(icon) = tag.strip_prefix("icon=") {
+            flush(&mut text, current, &mut spans);
+            spans.push(Span::Icon(icon.to_string(), current.clone()));
+            continue;
+        } else if let Some(style) = apply_tag(tag, current) {
+            flush(&mut text, current, &mut spans);
+            let name = tag.split('=').next().unwrap_or(tag).to_string();
+            styles.push((name, style));
+            continue;
+        }
+        text.push('[');
+        text.push_str(tag);
+        text.push(']');
+    }
+    text.push_str(rest);
+    flush(&mut text, &styles.last().unwrap().1, &mut spans);
+    spans
+}
+
+fn flush(text: &mut String, params: &FontParameters, spans: &mut Vec<Span>) {
+    if !text.is_empty() {
+        spans.push(Span::Text(std::mem::take(text), params.clone()));
+    }
+}
+
+/// Style inside an opening tag, `None` if the tag isn't known.
+fn apply_tag(tag: &str, params: &FontParameters) -> Option<FontParameters> {
+    let mut params = params.clone();
+    let (name, value) = match tag.split_once('=') {
+        Some((name, value)) => (name, Some(value)),
+        None => (tag, None)
+    };
+    match (name, value) {
+        ("b", None) => params.bold = true,
+        ("i", None) => params.italic = true,
+        ("u", None) => params.underline = true,
+        ("s", None) => params.strikeout = true,
+        ("color", Some(value)) => params.color = parse_color(value)?,
+        ("size", Some(value)) => {
+            params.size = value.parse::<u32>().ok().filter(|size| *size > 0)?.min(MAX_MARKUP_SIZE)
+        }
+        ("font", Some(value)) => params.font = value.to_string(),
+        _ => return None
+    }
+    Some(params)
+}
+
+/// `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`.
+fn parse_color(value: &str) -> Option<[f32; 4]> {
+    let hex = value.strip_prefix('#')?;
+    let digits = hex.chars().map(|c| c.to_digit(16)).collect::<Option<Vec<_>>>()?;
+    let channels = match digits.len() {
+        3 | 4 => digits.iter().map(|digit| digit * 17).collect::<Vec<_>>(),
+        6 | 8 => digits.chunks(2).map(|pair| pair[0] * 16 + pair[1]).collect(),
+        _ => return None
+    };
+    let alpha = channels.get(3).copied().unwrap_or(255);
+    Some([channels[0] as f32 / 255.0, channels[1] as f32 / 255.0, channels[2] as f32 / 255.0, alpha as f32 / 255.0])
+}
+
+/// Text run or icon placed by `FontManager::layout_rich`, relative to the point the text is aligned on.
+#[derive(Clone)]
+pub enum RichItem {
+    /// Line of text starting at `x` with its base line at `baseline`, see `FontManager::draw_line`.
+    Text { text: String, x: f32, baseline: f32, params: FontParameters },
+    /// Icon with its bounds as `[x, y, width, height]`.
+    Icon { name: String, bounds: [f32; 4] }
+}
+
+/// Rich text wrapped into lines, ready to be drawn.
+#[derive(Clone)]
+pub struct RichLayout {
+    pub items: Vec<RichItem>,
+    pub width: f32,
+    pub height: f32
+}
+
+// a character or an icon, with the span it comes from and its width, shared with `FontTexture::wrap`
+pub(super) struct Item {
+    pub(super) span: usize,
+    pub(super) character: Option<char>,
+    pub(super) width: f32
+}
+
+impl FontManager {
+    /// Lays out spans as a single block of text: the spans flow on the same lines and are
+    /// wrapped together to `params.width_limit`, which like the alignment is taken from `params`.
+    /// `icon_size` gives the size in pixels of the image of an icon, icons are scaled to the
+    /// height of the text above the base line.
+    pub fn layout_rich<I>(&mut self, spans: &[Span], params: &FontParameters, icon_size: I) -> RichLayout
+        where I: Fn(&str) -> Option<(u32, u32)> {
+
+        // unknown families fall back to the one of the text around the tags
+        let spans = spans.iter()
+            .map(|span| {
+                let mut span = span.clone();
+                let (Span::Text(_, span_params) | Span::Icon(_, span_params)) = &mut span;
+                if !self.has_font(&span_params.font) {
+                    span_params.font = params.font.clone();
+                }
+                span
+            })
+            .collect::<Vec<_>>();
+        let spans = &spans[..];
+
+        let mut items = Vec::new();
+        for (index, span) in spans.iter().enumerate() {
+            match span {
+                Span::Text(text, params) => {
+                    let em = params.size as f32 / 2.0;
+                    let advances = self.texture_for(params).advances(text);
+                    items.extend(text.chars().zip(advances).map(|(character, advance)| Item {
+                        span: index,
+                        character: Some(character),
+                        width: if character == SOFT_HYPHEN { 0.0 } else { advance * em }
+                    }));
+                }
+                Span::Icon(name, params) => {
+                    let height = params.size as f32 / 2.0 * BASELINE;
+                    let width = icon_size(name)
+                        .filter(|(_, h)| *h > 0)
+                        .map_or(height, |(w, h)| height * w as f32 / h as f32);
+                    items.push(Item { span: index, character: None, width });
+                }
+            }
+        }
+
+        let mut layout = RichLayout { items: Vec::new(), width: 0.0, height: 0.0 };
+        let mut lines = Vec::new();
+        for (start, end, soft) in break_lines(&items, params.width_limit) {
+            // span of the soft hyphen the line ends at, drawn as a hyphen
+            let soft = if soft { Some(items[end].span) } else { None };
+            // trailing whitespace isn't part of the line
+            let end = start + items[start .. end].iter()
+                .rposition(|item| !item.character.map_or(false, char::is_whitespace))
+                .map_or(0, |last| last + 1);
+            let width = items[start .. end].iter().map(|item| item.width).sum::<f32>();
+            lines.push((start, end, soft, width));
+            layout.width = layout.width.max(width);
+        }
+
+        let mut top = 0.0;
+        for (start, end, soft, width) in lines {
+            let line = &items[start .. end];
+            let em = match line.iter().map(|item| span_params(&spans[item.span]).size as f32 / 2.0).fold(0.0, f32::max) {
+                em if em > 0.0 => em,
+                _ => params.size as f32 / 2.0
+            };
+            let baseline = top + em * BASELINE;
+            let mut x = match params.align_horizontal {
+                TextAlignHorizontal::Left => 0.0,
+                TextAlignHorizontal::Right => -width,
+                TextAlignHorizontal::Center => -width / 2.0
+            };
+
+            // consecutive characters of a span are drawn as one run
+            let mut run: Option<(usize, String, f32)> = None;
+            for item in line {
+                let character = match item.character {
+                    Some(character) => character,
+                    None => {
+                        flush_run(&mut run, spans, baseline, &mut layout.items);
+                        let height = span_params(&spans[item.span]).size as f32 / 2.0 * BASELINE;
+                        if let Span::Icon(name, _) = &spans[item.span] {
+                            layout.items.push(RichItem::Icon {
+                                name: name.clone(),
+                                bounds: [x, baseline - height, item.width, height]
+                            });
+                        }
+                        x += item.width;
+                        continue;
+                    }
+                };
+                if run.as_ref().map_or(true, |(span, ..)| *span != item.span) {
+                    flush_run(&mut run, spans, baseline, &mut layout.items);
+                    run = Some((item.span, String::new(), x));
+                }
+                if character != SOFT_HYPHEN {
+                    run.as_mut().unwrap().1.push(character);
+                }
+                x += item.width;
+            }
+            if let Some(span) = soft {
+                if run.as_ref().map_or(true, |(run_span, ..)| *run_span != span) {
+                    flush_run(&mut run, spans, baseline, &mut layout.items);
+                    run = Some((span, String::new(), x));
+                }
+                run.as_mut().unwrap().1.push('-');
+            }
+            flush_run(&mut run, spans, baseline, &mut layout.items);
+            top += em;
+        }
+        layout.height = top;
+
+        let offset = match params.align_vertical {
+            TextAlignVertical::Top => 0.0,
+            TextAlignVertical::Bottom => -layout.height,
+            TextAlignVertical::Center => -layout.height / 2.0
+        };
+        for item in &mut layout.items {
+            match item {
+                RichItem::Text { baseline, .. } => *baseline += offset,
+                RichItem::Icon { bounds, .. } => bounds[1] += offset
+            }
+        }
+        layout
+    }
+}
+
+fn span_params(span: &Span) -> &FontParameters {
+    match span {
+        Span::Text(_, params) | Span::Icon(_, params) => params
+    }
+}
+
+fn flush_run(run: &mut Option<(usize, String, f32)>, spans: &[Span], baseline: f32, items: &mut Vec<RichItem>) {
+    if let Some((span, text, x)) = run.take() {
+        if !text.is_empty() {
+            items.push(RichItem::Text { text, x, baseline, params: span_params(&spans[span]).clone() });
+        }
+    }
+}
+
+/// Lines as ranges of items, and whether they end at a soft hyphen. Lines end at `\n`
+/// and are broken after whitespace, after hyphens or at soft hyphens when they are wider than `width`,
+/// anywhere if a word doesn't fit on its own.
+pub(super) fn break_lines(items: &[Item], width: f32) -> Vec<(usize, usize, bool)> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    let mut x = 0.0;
+    // end of the line and start of the next one at the last break opportunity
+    let mut last_break: Option<(usize, usize, bool)> = None;
+
+    for (index, item) in items.iter().enumerate() {
+        match item.character {
+            Some('\n') => {
+                lines.push((start, index, false));
+                start = index + 1;
+                x = 0.0;
+                last_break = None;
+                continue;
+            }
+            Some(SOFT_HYPHEN) => {
+                last_break = Some((index, index + 1, true));
+                continue;
+            }
+            Some(character) if character.is_whitespace() && character != NO_BREAK_SPACE => {
+                last_break = Some((index, index + 1, false));
+                x += item.width;
+                continue;
+            }
+            _ => {}
+        }
+
+        if x + item.width > width && index > start {
+            match last_break.take() {
+                Some((end, next, soft)) => {
+                    lines.push((start, end, soft));
+                    start = next;
+                }
+                None => {
+                    lines.push((start, index, false));
+                    start = index;
+                }
+            }
+            x = items[start .. index].iter().map(|item| item.width).sum();
+        }
+        x += item.width;
+        if item.character == Some('-') {
+            last_break = Some((index + 1, index + 1, false));
+        }
+    }
+    lines.push((start, items.len(), false));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(spans: &[Span]) -> Vec<(&str, bool)> {
+        spans.iter()
+            .map(|span| match span {
+                Span::Text(text, params) => (text.as_str(), params.bold),
+                Span::Icon(name, params) => (name.as_str(), params.bold)
+            })
+            .collect()
+    }
+
+    fn items(text: &str) -> Vec<Item> {
+        text.chars()
+            .map(|character| Item { span: 0, character: Some(character), width: 1.0 })
+            .collect()
+    }
+
+    #[test]
+    fn markup_tags() {
+        let spans = parse_markup("a[b]b[i]c[/i][/b]d", &FontParameters::default());
+        assert_eq!(texts(&spans), vec![("a", false), ("b", true), ("c", true), ("d", false)]);
+        assert!(matches!(&spans[2], Span::Text(_, params) if params.italic));
+        assert!(matches!(&spans[3], Span::Text(_, params) if !params.italic));
+    }
+
+    #[test]
+    fn markup_closes_inner_tags() {
+        let spans = parse_markup("[b]a[u]b[/b]c", &FontParameters::default());
+        assert!(matches!(&spans[2], Span::Text(text, params) if text == "c" && !params.bold && !params.underline));
+    }
+
+    #[test]
+    fn markup_kept_as_text() {
+        let params = FontParameters::default();
+        assert_eq!(texts(&parse_markup("[[b] a[/i]", &params)), vec![("[b] a[/i]", false)]);
+        assert_eq!(texts(&parse_markup("[wave]x[/wave]", &params)), vec![("[wave]x[/wave]", false)]);
+        assert_eq!(texts(&parse_markup("[size=0]x[b", &params)), vec![("[size=0]x[b", false)]);
+    }
+
+    #[test]
+    fn markup_icons() {
+        let spans = parse_markup("[color=#f00]1 [icon=coin][/color]", &FontParameters::default());
+        assert!(matches!(&spans[1], Span::Icon(name, params) if name == "coin" && params.color == [1.0, 0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn markup_size_clamped() {
+        let spans = parse_markup("[size=24]a[/size][size=100000]b", &FontParameters::default());
+        assert!(matches!(&spans[0], Span::Text(_, params) if params.size == 24));
+        assert!(matches!(&spans[1], Span::Text(_, params) if params.size == MAX_MARKUP_SIZE));
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(parse_color("#fff"), Some([1.0, 1.0, 1.0, 1.0]));
+        assert_eq!(parse_color("#0000"), Some([0.0, 0.0, 0.0, 0.0]));
+        assert_eq!(parse_color("#ff0000"), Some([1.0, 0.0, 0.0, 1.0]));
+        assert_eq!(parse_color("#00ff0033"), Some([0.0, 1.0, 0.0, 0.2]));
+        assert_eq!(parse_color("fff"), None);
+        assert_eq!(parse_color("#ggg"), None);
+        assert_eq!(parse_color("#12345"), None);
+    }
+
+    #[test]
+    fn lines_break_after_whitespace() {
+        assert_eq!(break_lines(&items("ab cd"), 3.0), vec![(0, 2, false), (3, 5, false)]);
+        assert_eq!(break_lines(&items("ab cd"), 10.0), vec![(0, 5, false)]);
+    }
+
+    #[test]
+    fn lines_break_at_newlines() {
+        assert_eq!(break_lines(&items("a\nb"), 10.0), vec![(0, 1, false), (2, 3, false)]);
+    }
+
+    #[test]
+    fn lines_break_long_words() {
+        assert_eq!(break_lines(&items("abcdef"), 3.0), vec![(0, 3, false), (3, 6, false)]);
+    }
+
+    #[test]
+    fn lines_break_at_hyphens() {
+        assert_eq!(break_lines(&items("ab\u{ad}cd"), 3.0), vec![(0, 2, true), (3, 5, false)]);
+        assert_eq!(break_lines(&items("ab-cd"), 3.0), vec![(0, 3, false), (3, 5, false)]);
+    }
+}
@@ -0,0 +1,168 @@
+//! Signed distance fields of glyph bitmaps, computed with the exact Euclidean distance
+//! transform of Felzenszwalb and Huttenlocher.
+
+/// Size distance field atlases are rasterized at, whatever the size the text is drawn at.
+pub const SDF_FONT_SIZE: u32 = 64;
+/// Distance in atlas pixels covered by the field on each side of the outline.
+pub const SDF_SPREAD: u32 = 8;
+
+const FAR: f32 = 1e20;
+
+/// Distance field of a coverage bitmap, grown by `SDF_SPREAD` pixels on each side.
+/// The outline is at 128, values go up to 255 inside and down to 0 outside.
+pub fn distance_field(buffer: &[u8], width: u32, rows: u32) -> Vec<u8> {
+    let spread = SDF_SPREAD as usize;
+    let (width, rows) = (width as usize, rows as usize);
+    let (field_width, field_rows) = (width + spread * 2, rows + spread * 2);
+
+    let coverage = |x: usize, y: usize| -> u8 {
+        if x < spread || y < spread || x >= width + spread || y >= rows + spread {
+            0
+        } else {
+            buffer[(y - spread) * width + x - spread]
+        }
+    };
+
+    // squared distances to the closest pixel inside and to the closest pixel outside
+    let mut to_inside = vec![0.0; field_width * field_rows];
+    let mut to_outside = vec![0.0; field_width * field_rows];
+    for y in 0 .. field_rows {
+        for x in 0 .. field_width {
+            let inside = coverage(x, y) >= 128;
+            to_inside[y * field_width + x] = if inside { 0.0 } else { FAR };
+            to_outside[y * field_width + x] = if inside { FAR } else { 0.0 };
+        }
+    }
+    transform(&mut to_inside, field_width, field_rows);
+    transform(&mut to_outside, field_width, field_rows);
+
+    let mut field = vec![0u8; field_width * field_rows];
+    for y in 0 .. field_rows {
+        for x in 0 .. field_width {
+            let index = y * field_width + x;
+            let value = coverage(x, y);
+            // positive outside, the outline runs between pixel centers
+            let distance = if value > 0 && value < 255 {
+                // anti-aliased pixels are on the outline, coverage tells how far
+                0.5 - value as f32 / 255.0
+            } else if to_inside[index] > 0.0 {
+                to_inside[index].sqrt() - 0.5
+            } else {
+                0.5 - to_outside[index].sqrt()
+            };
+            let normalized = 0.5 - distance / (SDF_SPREAD as f32 * 2.0);
+            field[index] = (normalized.clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+    }
+    field
+}
+
+/// Squared distance transform of a grid, along columns then rows.
+fn transform(grid: &mut [f32], width: usize, rows: usize) {
+    let length = width.max(rows);
+    let mut line = vec![0.0; length];
+    let mut output = vec![0.0; length];
+    let mut parabolas = vec![0; length];
+    let mut bounds = vec![0.0; length + 1];
+
+    for x in 0 .. width {
+        for y in 0 .. rows {
+            line[y] = grid[y * width + x];
+        }
+        transform_line(&line[.. rows], &mut output, &mut parabolas, &mut bounds);
+        for y in 0 .. rows {
+            grid[y * width + x] = output[y];
+        }
+    }
+    for y in 0 .. rows {
+        line[.. width].copy_from_slice(&grid[y * width .. (y + 1) * width]);
+        transform_line(&line[.. width], &mut output, &mut parabolas, &mut bounds);
+        grid[y * width .. (y + 1) * width].copy_from_slice(&output[.. width]);
+    }
+}
+
+/// One dimensional transform: lower envelope of the parabolas rooted at every sample.
+fn transform_line(samples: &[f32], output: &mut [f32], parabolas: &mut [usize], bounds: &mut [f32]) {
+    let mut k = 0;
+    parabolas[0] = 0;
+    bounds[0] = -FAR;
+    bounds[1] = FAR;
+    for q in 1 .. samples.len() {
+        // samples are finite, so the first parabola is never removed
+        let mut s;
+        loop {
+            let p = parabolas[k];
+            s = ((samples[q] + (q * q) as f32) - (samples[p] + (p * p) as f32)) / (2.0 * (q - p) as f32);
+            if s > bounds[k] {
+                break;
+            }
+            k -= 1;
+        }
+        k += 1;
+        parabolas[k] = q;
+        bounds[k] = s;
+        bounds[k + 1] = FAR;
+    }
+
+    k = 0;
+    for q in 0 .. samples.len() {
+        while bounds[k + 1] < q as f32 {
+            k += 1;
+        }
+        let p = parabolas[k];
+        output[q] = ((q as f32 - p as f32) * (q as f32 - p as f32)) + samples[p];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: u32 = 12;
+
+    /// Covered square from 3 to 8 on both axes, its border pixels have `edge` coverage.
+    fn square(edge: u8) -> Vec<u8> {
+        let mut buffer = vec![0; (SIZE * SIZE) as usize];
+        for y in 3 .. 9 {
+            for x in 3 .. 9 {
+                let border = x == 3 || y == 3 || x == 8 || y == 8;
+                buffer[y * SIZE as usize + x] = if border { edge } else { 255 };
+            }
+        }
+        buffer
+    }
+
+    fn row(field: &[u8], y: u32) -> &[u8] {
+        let width = (SIZE + SDF_SPREAD * 2) as usize;
+        &field[y as usize * width .. (y as usize + 1) * width]
+    }
+
+    #[test]
+    fn grows_by_spread() {
+        let field = distance_field(&square(255), SIZE, SIZE);
+        assert_eq!(field.len(), ((SIZE + SDF_SPREAD * 2) * (SIZE + SDF_SPREAD * 2)) as usize);
+        assert_eq!(field[0], 0);
+    }
+
+    #[test]
+    fn half_coverage_is_outline() {
+        let field = distance_field(&square(128), SIZE, SIZE);
+        let row = row(&field, SDF_SPREAD + 5);
+        assert_eq!(row[(SDF_SPREAD + 3) as usize], 128);
+        assert_eq!(row[(SDF_SPREAD + 8) as usize], 128);
+    }
+
+    #[test]
+    fn increases_towards_inside() {
+        let field = distance_field(&square(255), SIZE, SIZE);
+        let row = row(&field, SDF_SPREAD + 5);
+        let center = (SDF_SPREAD + 5) as usize;
+        assert!(row[.. center].windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(row[center ..].windows(2).all(|pair| pair[0] >= pair[1]));
+
+        for (x, value) in row.iter().enumerate() {
+            let inside = x >= (SDF_SPREAD + 3) as usize && x <= (SDF_SPREAD + 8) as usize;
+            assert_eq!(*value > 128, inside, "pixel {}", x);
+        }
+    }
+}
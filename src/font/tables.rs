@@ -0,0 +1,58 @@
+//! Reading of raw OpenType tables for the metrics rusttype doesn't expose.
+
+/// Underline and strikeout placement, in font units above the base line.
+#[derive(Copy, Clone, Debug)]
+pub struct Decorations {
+    /// Top of the underline, from the `post` table.
+    pub underline_position: i16,
+    pub underline_thickness: i16,
+    /// Top of the strikeout, from the `OS/2` table.
+    pub strikeout_position: i16,
+    pub strikeout_thickness: i16
+}
+
+impl Decorations {
+    /// `None` unless the font has both tables with non-zero thicknesses.
+    pub fn parse(font: &[u8]) -> Option<Decorations> {
+        let post = find_table(font, b"post")?;
+        let os2 = find_table(font, b"OS/2")?;
+        let decorations = Decorations {
+            underline_position: read_u16(post, 8)? as i16,
+            underline_thickness: read_u16(post, 10)? as i16,
+            strikeout_thickness: read_u16(os2, 26)? as i16,
+            strikeout_position: read_u16(os2, 28)? as i16
+        };
+        if decorations.underline_thickness <= 0 || decorations.strikeout_thickness <= 0 {
+            return None;
+        }
+        Some(decorations)
+    }
+}
+
+/// Table of the first font of a file or collection.
+pub fn find_table<'a>(font: &'a [u8], tag: &[u8]) -> Option<&'a [u8]> {
+    let directory = if font.get(0 .. 4)? == b"ttcf" {
+        read_u32(font, 12)? as usize
+    } else {
+        0
+    };
+    for record in 0 .. read_u16(font, directory + 4)? as usize {
+        let record = directory + 12 + record * 16;
+        if font.get(record .. record + 4)? == tag {
+            let offset = read_u32(font, record + 8)? as usize;
+            let length = read_u32(font, record + 12)? as usize;
+            return font.get(offset .. offset + length);
+        }
+    }
+    None
+}
+
+pub fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset .. offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+pub fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset .. offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
@@ -14,6 +14,7 @@ use glium::texture::SrgbTexture2d;
 use glium::uniforms::MagnifySamplerFilter;
 use crate::audio::SoundSystem;
 use crate::font::{FontParameters, TextAlignHorizontal};
+use crate::material::Material;
 use crate::render::Canvas;
 
 use crate::window::{Context, Handler};
@@ -27,6 +28,8 @@ mod textures;
 mod render;
 mod font;
 mod audio;
+mod sprite;
+mod material;
 
 struct WindowContext {
     start: Instant,
@@ -70,13 +73,10 @@ impl Handler<WindowContext> for WindowHandler {
 
         let (x, y) = canvas.dimensions();
 
-        let shader = canvas.shaders().borrow().default();
-        let uniforms = uniform! {
-            mat: Into::<[[f32; 4]; 4]>::into(canvas.viewport())
-        };
+        let material = Material::from_shader(canvas.shaders(), "default").unwrap();
         let params = DrawParameters::default();
 
-        canvas.rect([40.0, 20.0, 100.0, 20.0], [1.0, 0.0, 0.0, 1.0], &*shader, &uniforms, &params);
+        canvas.rect_with_material([40.0, 20.0, 100.0, 20.0], [1.0, 0.0, 0.0, 1.0], &material, &params).unwrap();
 
         canvas.text("Привет, мир!", x / 2.0, y - 50.0, &FontParameters {
             color: [r, g, b, 1.0],
@@ -114,7 +114,7 @@ impl Handler<WindowContext> for WindowHandler {
         if let Some(key) = input.virtual_keycode {
             if key == VirtualKeyCode::Back && input.state == ElementState::Pressed {
 
-                let _ = context.sound_system.play_streaming_file("resources/sounds/laser.ogg")
+                let _ = context.sound_system.play_streaming_bytes(sound!("laser"))
                     .expect("Error playing sound");
                 println!("pew-pew");
             }
@@ -0,0 +1,265 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use glium::Program;
+use glium::uniforms::{AsUniformValue, UniformType, Uniforms, UniformValue};
+use crate::shaders::{ShaderError, ShaderManager};
+use crate::textures::{TextureManager, TextureUniform};
+
+/// Name of the projection matrix uniform filled in by `Canvas` unless the material sets it.
+pub const VIEWPORT_UNIFORM: &str = "mat";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UniformData {
+    Float(f32),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    Mat4([[f32; 4]; 4]),
+    Int(i32),
+    Bool(bool),
+    /// Texture, array or cubemap registered in `TextureManager` under this name, it must match
+    /// the sampler type of the uniform.
+    Texture(String)
+}
+
+impl UniformData {
+    fn is_usable_with(&self, ty: &UniformType) -> bool {
+        match self {
+            UniformData::Texture(_) => matches!(ty, UniformType::Sampler2d | UniformType::Sampler2dArray | UniformType::SamplerCube),
+            UniformData::Float(v) => UniformValue::Float(*v).is_usable_with(ty),
+            UniformData::Vec2(v) => UniformValue::Vec2(*v).is_usable_with(ty),
+            UniformData::Vec3(v) => UniformValue::Vec3(*v).is_usable_with(ty),
+            UniformData::Vec4(v) => UniformValue::Vec4(*v).is_usable_with(ty),
+            UniformData::Mat4(v) => UniformValue::Mat4(*v).is_usable_with(ty),
+            UniformData::Int(v) => UniformValue::SignedInt(*v).is_usable_with(ty),
+            UniformData::Bool(v) => UniformValue::Bool(*v).is_usable_with(ty)
+        }
+    }
+}
+
+impl From<f32> for UniformData {
+    fn from(v: f32) -> Self {
+        UniformData::Float(v)
+    }
+}
+
+impl From<[f32; 2]> for UniformData {
+    fn from(v: [f32; 2]) -> Self {
+        UniformData::Vec2(v)
+    }
+}
+
+impl From<[f32; 3]> for UniformData {
+    fn from(v: [f32; 3]) -> Self {
+        UniformData::Vec3(v)
+    }
+}
+
+impl From<[f32; 4]> for UniformData {
+    fn from(v: [f32; 4]) -> Self {
+        UniformData::Vec4(v)
+    }
+}
+
+impl From<[[f32; 4]; 4]> for UniformData {
+    fn from(v: [[f32; 4]; 4]) -> Self {
+        UniformData::Mat4(v)
+    }
+}
+
+impl From<cgmath::Matrix4<f32>> for UniformData {
+    fn from(v: cgmath::Matrix4<f32>) -> Self {
+        UniformData::Mat4(v.into())
+    }
+}
+
+impl From<i32> for UniformData {
+    fn from(v: i32) -> Self {
+        UniformData::Int(v)
+    }
+}
+
+impl From<bool> for UniformData {
+    fn from(v: bool) -> Self {
+        UniformData::Bool(v)
+    }
+}
+
+/// Shader with its uniform values, checked against the uniforms the program declares.
+/// The program is looked up in `ShaderManager` on every use so hot reloaded shaders apply.
+///
+/// ```ignore
+/// let material = Material::from_shader(canvas.shaders(), "textured")?
+///     .with("tex", UniformData::Texture("background".into()))?;
+/// canvas.textured_rect_with_material(bounds, [0.0, 0.0, 1.0, 1.0], color, &material, &params)?;
+/// ```
+#[derive(Clone)]
+pub struct Material {
+    shaders: Rc<RefCell<ShaderManager>>,
+    shader: String,
+    defines: Vec<String>,
+    values: HashMap<String, UniformData>
+}
+
+impl Material {
+    pub fn from_shader<N>(shaders: Rc<RefCell<ShaderManager>>, name: N) -> Result<Material, MaterialError>
+        where N: AsRef<str> {
+
+        let name = name.as_ref();
+        if shaders.borrow().try_get(name).is_none() {
+            return Err(ShaderError::Missing(name.into()).into());
+        }
+        Ok(Material {
+            shaders,
+            shader: name.into(),
+            defines: Vec::new(),
+            values: HashMap::new()
+        })
+    }
+
+    /// Material drawn with a permutation of a shader, compiled by `ShaderManager::variant`.
+    pub fn from_variant<N>(shaders: Rc<RefCell<ShaderManager>>, name: N, defines: &[&str]) -> Result<Material, MaterialError>
+        where N: AsRef<str> {
+
+        shaders.borrow_mut().variant(name.as_ref(), defines)?;
+        Ok(Material {
+            shaders,
+            shader: name.as_ref().into(),
+            defines: defines.iter().map(|define| define.to_string()).collect(),
+            values: HashMap::new()
+        })
+    }
+
+    /// Current program of the shader, reloads replace it but never remove it.
+    pub fn program(&self) -> Rc<Box<Program>> {
+        let shaders = self.shaders.borrow();
+        let program = if self.defines.is_empty() {
+            shaders.try_get(&self.shader)
+        } else {
+            let defines: Vec<&str> = self.defines.iter().map(String::as_str).collect();
+            shaders.try_variant(&self.shader, &defines)
+        };
+        program.expect("Material shader is missing")
+    }
+
+    /// Sets a uniform, like `set` but consumes and returns the material.
+    pub fn with<N, V>(mut self, name: N, value: V) -> Result<Self, MaterialError>
+        where N: AsRef<str>, V: Into<UniformData> {
+
+        self.set(name, value)?;
+        Ok(self)
+    }
+
+    /// Sets a uniform declared by the program. The kind of a texture is only known once it is
+    /// loaded, so textures are checked against the sampler type by `bind`.
+    pub fn set<N, V>(&mut self, name: N, value: V) -> Result<(), MaterialError>
+        where N: AsRef<str>, V: Into<UniformData> {
+
+        let name = name.as_ref();
+        let value = value.into();
+        let program = self.program();
+        let uniform = program.get_uniform(name)
+            .ok_or_else(|| MaterialError::UnknownUniform(name.into()))?;
+        if !value.is_usable_with(&uniform.ty) {
+            return Err(MaterialError::TypeMismatch(name.into(), uniform.ty));
+        }
+        self.values.insert(name.into(), value);
+        Ok(())
+    }
+
+    pub fn get<N>(&self, name: N) -> Option<&UniformData> where N: AsRef<str> {
+        self.values.get(name.as_ref())
+    }
+
+    pub fn remove<N>(&mut self, name: N) -> Option<UniformData> where N: AsRef<str> {
+        self.values.remove(name.as_ref())
+    }
+
+    /// Uniforms declared by the program that have no value yet, except the viewport matrix.
+    pub fn missing(&self) -> Vec<String> {
+        self.program().uniforms()
+            .map(|(name, _)| name)
+            .filter(|name| name.as_str() != VIEWPORT_UNIFORM && !self.values.contains_key(*name))
+            .cloned()
+            .collect()
+    }
+
+    /// Resolves the textures and returns the uniforms to draw with, `viewport` is used for
+    /// `VIEWPORT_UNIFORM` unless the material has its own value. Values are checked against
+    /// the current program, those it no longer declares after a reload are ignored.
+    pub fn bind(&self, textures: &TextureManager, viewport: [[f32; 4]; 4]) -> Result<MaterialUniforms<'_>, MaterialError> {
+        let program = self.program();
+        let mut resolved = Vec::new();
+        for (name, value) in &self.values {
+            let uniform = match program.get_uniform(name) {
+                Some(uniform) => uniform,
+                None => continue
+            };
+            let usable = match value {
+                UniformData::Texture(texture) => {
+                    let texture = textures.uniform(texture);
+                    let usable = texture.as_uniform_value().is_usable_with(&uniform.ty);
+                    resolved.push((name.as_str(), texture));
+                    usable
+                }
+                value => value.is_usable_with(&uniform.ty)
+            };
+            if !usable {
+                return Err(MaterialError::TypeMismatch(name.clone(), uniform.ty));
+            }
+        }
+        Ok(MaterialUniforms {
+            material: self,
+            textures: resolved,
+            viewport
+        })
+    }
+}
+
+/// Uniforms of a `Material` with its textures resolved, see `Material::bind`.
+pub struct MaterialUniforms<'m> {
+    material: &'m Material,
+    textures: Vec<(&'m str, TextureUniform)>,
+    viewport: [[f32; 4]; 4]
+}
+
+impl<'m> Uniforms for MaterialUniforms<'m> {
+    fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut visit: F) {
+        if !self.material.values.contains_key(VIEWPORT_UNIFORM) {
+            visit(VIEWPORT_UNIFORM, UniformValue::Mat4(self.viewport));
+        }
+        for (name, value) in &self.material.values {
+            let value = match value {
+                UniformData::Float(v) => UniformValue::Float(*v),
+                UniformData::Vec2(v) => UniformValue::Vec2(*v),
+                UniformData::Vec3(v) => UniformValue::Vec3(*v),
+                UniformData::Vec4(v) => UniformValue::Vec4(*v),
+                UniformData::Mat4(v) => UniformValue::Mat4(*v),
+                UniformData::Int(v) => UniformValue::SignedInt(*v),
+                UniformData::Bool(v) => UniformValue::Bool(*v),
+                UniformData::Texture(_) => continue
+            };
+            visit(name, value);
+        }
+        for (name, texture) in &self.textures {
+            visit(name, texture.as_uniform_value());
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum MaterialError {
+    /// The program has no active uniform with this name.
+    UnknownUniform(String),
+    /// The value doesn't fit the type the uniform is declared with.
+    TypeMismatch(String, UniformType),
+    /// The shader is not registered or its variant failed to compile.
+    Shader(ShaderError)
+}
+
+impl From<ShaderError> for MaterialError {
+    fn from(e: ShaderError) -> Self {
+        MaterialError::Shader(e)
+    }
+}
@@ -5,9 +5,10 @@ use cgmath::{Matrix4, Point3, Transform};
 use glium::index::PrimitiveType;
 use glium::{Display, DrawParameters, IndexBuffer, Program, Rect, Surface, VertexBuffer};
 use glium::uniforms::Uniforms;
-use crate::font::{FontManager, FontParameters};
+use crate::font::{parse_markup, FontManager, FontParameters, RichItem, RichLayout, TextAlignHorizontal};
+use crate::material::{Material, MaterialError};
 use crate::shaders::ShaderManager;
-use crate::textures::TextureManager;
+use crate::textures::{TextureManager, TextureRegion};
 
 pub struct DrawBuffer {
     capacity: usize,
@@ -315,18 +316,20 @@ impl<S> Canvas<S> where S: Surface {
                                   params: &DrawParameters)
         where B: Into<[f32; 4]>, C: Into<[f32; 4]>, U: Uniforms {
 
+        self.textured_rect_uv(bounds, [0.0, 0.0, 1.0, 1.0], color, program, uniforms, params)
+    }
+
+    pub fn textured_rect_uv<B, C, U>(&mut self, bounds: B, uv: [f32; 4], color: C, program: &Program,
+                                     uniforms: &U, params: &DrawParameters)
+        where B: Into<[f32; 4]>, C: Into<[f32; 4]>, U: Uniforms {
+
         let bounds = bounds.into();
         let color = color.into();
 
         DrawBuffer::draw_once(
             &PrimitiveType::TriangleFan, false, true, &self.display.clone(),
             &mut self.target, program, uniforms, params,
-            vec! [
-                Vertex::pos([bounds[0], bounds[1], 0.0]).color(color).uv([0.0, 0.0]),
-                Vertex::pos([bounds[0] + bounds[2], bounds[1], 0.0]).color(color).uv([1.0, 0.0]),
-                Vertex::pos([bounds[0] + bounds[2], bounds[1] + bounds[3], 0.0]).color(color).uv([1.0, 1.0]),
-                Vertex::pos([bounds[0], bounds[1] + bounds[3], 0.0]).color(color).uv([0.0, 1.0]),
-            ]
+            quad_vertices(bounds, uv, color)
         )
     }
 
@@ -334,19 +337,80 @@ impl<S> Canvas<S> where S: Surface {
                                        params: &DrawParameters)
         where B: Into<[f32; 4]>, C: Into<[f32; 4]>, T: AsRef<str> {
 
-        let texture = self.textures().borrow().get(texture);
+        let texture = self.textures().borrow().uniform(texture);
         let mat = self.viewport();
 
         let uniforms = glium::uniform! {
             mat: Into::<[[f32; 4]; 4]>::into(mat),
-            tex: texture.sampled()
-                .magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest)
-                .minify_filter(glium::uniforms::MinifySamplerFilter::NearestMipmapNearest)
+            tex: texture
         };
 
         self.textured_rect(bounds, color, program, &uniforms, &params);
     }
 
+    /// Draws a region of an atlas page (or a whole texture) from `TextureManager`.
+    pub fn fill_region<T, B, C>(&mut self, region: T, bounds: B, color: C, program: &Program,
+                                params: &DrawParameters)
+        where B: Into<[f32; 4]>, C: Into<[f32; 4]>, T: AsRef<str> {
+
+        let region = self.textures().borrow().get_region(region);
+
+        self.fill_texture_region(&region, bounds, color, program, params);
+    }
+
+    pub fn fill_texture_region<B, C>(&mut self, region: &TextureRegion, bounds: B, color: C, program: &Program,
+                                     params: &DrawParameters)
+        where B: Into<[f32; 4]>, C: Into<[f32; 4]> {
+
+        let texture = self.textures().borrow().uniform(&region.texture);
+        let mat = self.viewport();
+
+        let uniforms = glium::uniform! {
+            mat: Into::<[[f32; 4]; 4]>::into(mat),
+            tex: texture
+        };
+
+        self.textured_rect_uv(bounds, region.uv, color, program, &uniforms, params);
+    }
+
+    /// Draws many regions, batching the ones that share an atlas page into a single draw call.
+    pub fn fill_regions<'r, I, C>(&mut self, regions: I, color: C, program: &Program, params: &DrawParameters)
+        where I: IntoIterator<Item=(&'r TextureRegion, [f32; 4])>, C: Into<[f32; 4]> {
+
+        let color = color.into();
+        let mut batches: Vec<(&str, Vec<Vertex>, Vec<u32>)> = Vec::new();
+
+        for (region, bounds) in regions {
+            let index = match batches.iter().position(|(texture, ..)| *texture == region.texture) {
+                Some(index) => index,
+                None => {
+                    batches.push((&region.texture, Vec::new(), Vec::new()));
+                    batches.len() - 1
+                }
+            };
+            let (_, vertices, indices) = &mut batches[index];
+            let offset = vertices.len() as u32;
+            vertices.extend(quad_vertices(bounds, region.uv, color));
+            indices.extend([0, 1, 2, 2, 3, 0].iter().map(|i| offset + i));
+        }
+
+        let mat = self.viewport();
+        let display = self.display.clone();
+
+        for (texture, vertices, indices) in batches {
+            let texture = self.textures().borrow().uniform(texture);
+            let uniforms = glium::uniform! {
+                mat: Into::<[[f32; 4]; 4]>::into(mat),
+                tex: texture
+            };
+
+            let mut buffer = DrawBuffer::with_capacity(vertices.len());
+            buffer.start_drawing(&PrimitiveType::TrianglesList, false, true);
+            buffer.add_multiple_vertices(vertices, indices);
+            buffer.draw(&display, &mut self.target, program, &uniforms, params);
+        }
+    }
+
     pub fn generic_shape<U>(&mut self, ty: &PrimitiveType, vertices: Vec<Vertex>, texture: bool,
                             normal: bool, program: &Program, uniforms: &U, params: &DrawParameters) where U: Uniforms {
         DrawBuffer::draw_once(ty, normal, texture, &self.display.clone(),
@@ -354,6 +418,42 @@ impl<S> Canvas<S> where S: Surface {
         )
     }
 
+    pub fn rect_with_material<B, C>(&mut self, bounds: B, color: C, material: &Material, params: &DrawParameters)
+        -> Result<(), MaterialError> where B: Into<[f32; 4]>, C: Into<[f32; 4]> {
+
+        let textures = self.textures();
+        let uniforms = material.bind(&textures.borrow(), self.viewport().into())?;
+        self.rect(bounds, color, &material.program(), &uniforms, params);
+        Ok(())
+    }
+
+    pub fn frame_with_material<B, C>(&mut self, bounds: B, color: C, material: &Material, params: &DrawParameters)
+        -> Result<(), MaterialError> where B: Into<[f32; 4]>, C: Into<[f32; 4]> {
+
+        let textures = self.textures();
+        let uniforms = material.bind(&textures.borrow(), self.viewport().into())?;
+        self.frame(bounds, color, &material.program(), &uniforms, params);
+        Ok(())
+    }
+
+    pub fn textured_rect_with_material<B, C>(&mut self, bounds: B, uv: [f32; 4], color: C, material: &Material,
+                                             params: &DrawParameters)
+        -> Result<(), MaterialError> where B: Into<[f32; 4]>, C: Into<[f32; 4]> {
+
+        let textures = self.textures();
+        let uniforms = material.bind(&textures.borrow(), self.viewport().into())?;
+        self.textured_rect_uv(bounds, uv, color, &material.program(), &uniforms, params);
+        Ok(())
+    }
+
+    pub fn shape_with_material(&mut self, ty: &PrimitiveType, vertices: Vec<Vertex>, texture: bool, normal: bool,
+                               material: &Material, params: &DrawParameters) -> Result<(), MaterialError> {
+        let textures = self.textures();
+        let uniforms = material.bind(&textures.borrow(), self.viewport().into())?;
+        self.generic_shape(ty, vertices, texture, normal, &material.program(), &uniforms, params);
+        Ok(())
+    }
+
     pub fn get_text_size<T>(&self, text: T, params: &FontParameters) -> (f32, f32) where T: AsRef<str> {
         let fonts = self.fonts();
         let mut fonts = fonts.borrow_mut();
@@ -371,7 +471,96 @@ impl<S> Canvas<S> where S: Surface {
         fonts.draw_string(&mut self.target, text, x, y, viewport, params);
     }
 
+    /// Size of text with markup, see `rich_text`.
+    pub fn get_rich_text_size<T>(&self, markup: T, params: &FontParameters) -> (f32, f32) where T: AsRef<str> {
+        let layout = self.layout_rich_text(markup.as_ref(), params);
+        (layout.width, layout.height)
+    }
+
+    /// Draws text with markup for colors, styles and icons, like
+    /// `"[b]Gold:[/b] [color=#fc0]120[/color] [icon=coin]"`. See `font::parse_markup` for the tags.
+    /// `params` is the style outside of tags, and sets the wrapping and alignment of the whole text.
+    pub fn rich_text<T>(&mut self, markup: T, x: f32, y: f32, params: &FontParameters) where T: AsRef<str> {
+        let layout = self.layout_rich_text(markup.as_ref(), params);
+        let viewport = self.viewport();
+        let fonts = self.fonts();
+        let textures = self.textures();
+
+        let mut icons = Vec::new();
+        for item in layout.items {
+            match item {
+                RichItem::Text { text, x: offset, baseline, params } => {
+                    fonts.borrow_mut().draw_line(&mut self.target, &text, x + offset, y + baseline, viewport, &params);
+                }
+                // icons without a region leave their space blank
+                RichItem::Icon { name, bounds } => if let Some(region) = textures.borrow().region(&name) {
+                    icons.push((region, [x + bounds[0], y + bounds[1], bounds[2], bounds[3]]));
+                }
+            }
+        }
+
+        if !icons.is_empty() {
+            let program = self.shaders().borrow().textured();
+            let color = [1.0, 1.0, 1.0, params.color[3]];
+            self.fill_regions(icons.iter().map(|(region, bounds)| (region, *bounds)), color, &program, &DrawParameters {
+                blend: glium::Blend::alpha_blending(),
+                scissor: params.scissor,
+                .. Default::default()
+            });
+        }
+    }
+
+    fn layout_rich_text(&self, markup: &str, params: &FontParameters) -> RichLayout {
+        let spans = parse_markup(markup, params);
+        let textures = self.textures();
+        let textures = textures.borrow();
+        let mut fonts = self.fonts.borrow_mut();
+        fonts.layout_rich(&spans, params, |name| textures.region(name).map(|region| region.size))
+    }
+
+    /// Draws the errors of shaders that failed to reload over the frame.
+    pub fn shader_errors(&mut self) {
+        let errors = self.shaders().borrow().errors.values().cloned().collect::<Vec<_>>();
+        if errors.is_empty() {
+            return;
+        }
+
+        let (width, height) = self.dimensions();
+        let program = self.shaders().borrow().default();
+        let uniforms = glium::uniform! {
+            mat: Into::<[[f32; 4]; 4]>::into(self.viewport())
+        };
+        self.rect([0.0, 0.0, width, height], [0.0, 0.0, 0.0, 0.75], &*program, &uniforms, &DrawParameters {
+            blend: glium::Blend::alpha_blending(),
+            .. Default::default()
+        });
+
+        let params = FontParameters {
+            size: 16,
+            color: [1.0, 0.3, 0.3, 1.0],
+            align_horizontal: TextAlignHorizontal::Left,
+            .. Default::default()
+        };
+        let mut y = 10.0;
+        for line in errors.iter().flat_map(|error| error.lines()) {
+            self.text(line, 10.0, y, &params);
+            y += self.get_text_size(line, &params).1.max(16.0) + 2.0;
+            if y > height {
+                break;
+            }
+        }
+    }
+
     pub fn into_inner(self) -> S {
         self.target
     }
-}
\ No newline at end of file
+}
+
+fn quad_vertices(bounds: [f32; 4], uv: [f32; 4], color: [f32; 4]) -> Vec<Vertex> {
+    vec! [
+        Vertex::pos([bounds[0], bounds[1], 0.0]).color(color).uv([uv[0], uv[1]]),
+        Vertex::pos([bounds[0] + bounds[2], bounds[1], 0.0]).color(color).uv([uv[0] + uv[2], uv[1]]),
+        Vertex::pos([bounds[0] + bounds[2], bounds[1] + bounds[3], 0.0]).color(color).uv([uv[0] + uv[2], uv[1] + uv[3]]),
+        Vertex::pos([bounds[0], bounds[1] + bounds[3], 0.0]).color(color).uv([uv[0], uv[1] + uv[3]]),
+    ]
+}
@@ -0,0 +1,52 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use glium::{Display, Program};
+use glium::program::Binary;
+
+/// Identifies a program binary: the driver that produced it and the exact sources.
+/// FNV-1a is used so that keys stay the same across builds.
+pub fn key(display: &Display, sources: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let driver = [
+        display.get_opengl_vendor_string(),
+        display.get_opengl_renderer_string(),
+        display.get_opengl_version_string()
+    ];
+    for part in driver.iter().chain(sources.iter()) {
+        for byte in part.bytes().chain(std::iter::once(0)) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+fn path(dir: &Path, key: u64) -> PathBuf {
+    dir.join(format!("{:016x}.bin", key))
+}
+
+/// Program from a cached binary, `None` if there is none or the driver rejects it.
+pub fn load(dir: &Path, display: &Display, key: u64) -> Option<Program> {
+    let bytes = fs::read(path(dir, key)).ok()?;
+    if bytes.len() < 4 {
+        return None;
+    }
+    let binary = Binary {
+        format: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        content: bytes[4 ..].to_vec()
+    };
+    Program::new(display, binary).ok()
+}
+
+/// Stores the binary of a program, silently skipped when the driver or the disk doesn't allow it.
+pub fn store(dir: &Path, key: u64, program: &Program) {
+    let binary = match program.get_binary() {
+        Ok(binary) => binary,
+        Err(_) => return
+    };
+    let mut bytes = binary.format.to_le_bytes().to_vec();
+    bytes.extend_from_slice(&binary.content);
+    if fs::create_dir_all(dir).is_ok() {
+        let _ = fs::write(path(dir, key), bytes);
+    }
+}
@@ -1,25 +1,18 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::time::SystemTime;
 use glium::{Display, Program, ProgramCreationError};
+use glium::program::ShaderType;
 use msgbox::IconType;
 
-pub fn compile(display: &Display, vertex: &str, fragment: &str, geometry: Option<&str>) -> Program {
-    match Program::from_source(display, vertex, fragment, geometry) {
-        Ok(program) => program,
-        Err(e) => {
-            match e {
-                ProgramCreationError::CompilationError(message, shader) => {
-                    let message = format!("Error compiling {:?} Shader from source:\n\n{}", shader, message);
-                    msgbox::create("Shader compilation error", &message, IconType::Error).unwrap();
-                    Err(ProgramCreationError::CompilationError(message, shader)).unwrap()
-                }
-                other => {
-                    Err(other).unwrap()
-                }
-            }
-        }
-    }
-}
+mod cache;
+mod preprocessor;
+
+pub use preprocessor::{preprocess, Preprocessed};
 
 #[macro_export]
 macro_rules! shader {
@@ -35,37 +28,479 @@ macro_rules! shader {
 
 pub struct ShaderManager {
     display: Display,
-    programs: HashMap<String, Rc<Box<Program>>>
+    programs: HashMap<String, Rc<Box<Program>>>,
+    sources: HashMap<String, ShaderSource>,
+    variants: HashMap<String, Variant>,
+    /// Directory `#include` directives are resolved against.
+    pub include_dir: PathBuf,
+    /// Directory where compiled program binaries are kept to skip compilation on the next
+    /// start, keyed by the driver and the preprocessed sources. `None` disables the cache.
+    pub binary_cache: Option<PathBuf>,
+    /// Whether compilation errors of `load` and `variant` are also shown in a blocking popup.
+    /// Disabled by default.
+    pub error_popup: bool,
+    /// Whether `reload_changed` recompiles shaders loaded from files. Enabled by default in debug builds.
+    pub hot_reload: bool,
+    /// Last reload error of every shader or variant that currently fails to compile.
+    pub errors: HashMap<String, String>
+}
+
+/// Sources of a registered shader, with the modification time of its files at the last compilation.
+struct ShaderSource {
+    vertex: Stage,
+    fragment: Stage,
+    geometry: Option<Stage>,
+    includes: Vec<PathBuf>,
+    modified: Option<SystemTime>
+}
+
+enum Stage {
+    File(PathBuf),
+    /// Source built into the binary, with the file name used in error messages.
+    Embedded(&'static str, &'static str)
+}
+
+/// Permutation of a shader compiled with extra `#define`s, see `ShaderManager::variant`.
+struct Variant {
+    shader: String,
+    defines: Vec<String>,
+    program: Rc<Box<Program>>
+}
+
+impl Stage {
+    fn name(&self) -> Cow<'_, str> {
+        match self {
+            Stage::File(path) => path.to_string_lossy(),
+            Stage::Embedded(name, _) => Cow::Borrowed(name)
+        }
+    }
+
+    fn read(&self) -> io::Result<Cow<'static, str>> {
+        match self {
+            Stage::File(path) => Ok(Cow::Owned(fs::read_to_string(path)?)),
+            Stage::Embedded(_, source) => Ok(Cow::Borrowed(source))
+        }
+    }
+}
+
+impl ShaderSource {
+    fn stages(&self) -> impl Iterator<Item=&Stage> {
+        std::iter::once(&self.vertex).chain(std::iter::once(&self.fragment)).chain(self.geometry.iter())
+    }
+
+    /// Latest modification time of the files, including the `#include`d ones.
+    fn modified(&self) -> Option<SystemTime> {
+        self.stages()
+            .filter_map(|stage| match stage {
+                Stage::File(path) => Some(path),
+                Stage::Embedded(..) => None
+            })
+            .chain(self.includes.iter())
+            .filter_map(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+            .max()
+    }
 }
 
 impl ShaderManager {
     pub fn new(display: &Display) -> ShaderManager {
-        let mut programs = HashMap::new();
-        programs.insert("font".into(), Rc::new(Box::new(
-            shader!(display, "font")
-        )));
-        programs.insert("default".into(), Rc::new(Box::new(
-            shader!(display, "default")
-        )));
-        programs.insert("textured".into(), Rc::new(Box::new(
-            shader!(display, "textured")
-        )));
-
-        ShaderManager {
+        let mut manager = ShaderManager {
             display: display.clone(),
-            programs
+            programs: HashMap::new(),
+            sources: HashMap::new(),
+            variants: HashMap::new(),
+            include_dir: PathBuf::from("resources/shaders"),
+            binary_cache: Some(std::env::temp_dir().join(concat!(env!("CARGO_PKG_NAME"), "-shaders"))),
+            error_popup: false,
+            hot_reload: cfg!(debug_assertions),
+            errors: HashMap::new()
+        };
+
+        manager.register_embedded("font",
+            ("font.vsh", include_str!("../../resources/shaders/font.vsh")),
+            ("font.fsh", include_str!("../../resources/shaders/font.fsh"))
+        );
+        manager.register_embedded("default",
+            ("default.vsh", include_str!("../../resources/shaders/default.vsh")),
+            ("default.fsh", include_str!("../../resources/shaders/default.fsh"))
+        );
+        manager.register_embedded("textured",
+            ("textured.vsh", include_str!("../../resources/shaders/textured.vsh")),
+            ("textured.fsh", include_str!("../../resources/shaders/textured.fsh"))
+        );
+        manager
+    }
+
+    fn register_embedded(&mut self, name: &str, vertex: (&'static str, &'static str), fragment: (&'static str, &'static str)) {
+        let source = ShaderSource {
+            vertex: Stage::Embedded(vertex.0, vertex.1),
+            fragment: Stage::Embedded(fragment.0, fragment.1),
+            geometry: None,
+            includes: Vec::new(),
+            modified: None
+        };
+        if let Err(e) = self.register(name.into(), source) {
+            panic!("Built-in shader `{}` failed to compile:\n{}", name, e.report());
+        }
+    }
+
+    fn register(&mut self, name: String, mut source: ShaderSource) -> Result<Rc<Box<Program>>, ShaderError> {
+        let (program, includes) = self.build(&source, &[]).map_err(|e| self.report(e))?;
+        source.includes = includes;
+        source.modified = source.modified();
+
+        let program = Rc::new(Box::new(program));
+        self.programs.insert(name.clone(), program.clone());
+        self.sources.insert(name, source);
+        Ok(program)
+    }
+
+    /// Shows the popup if `error_popup` is set.
+    fn report(&self, error: ShaderError) -> ShaderError {
+        if self.error_popup {
+            error.show_popup();
+        }
+        error
+    }
+
+    /// Preprocesses and compiles every stage, compilation errors point to the original files.
+    fn build(&self, source: &ShaderSource, defines: &[&str]) -> Result<(Program, Vec<PathBuf>), ShaderError> {
+        let stage = |stage: &Stage| -> Result<Preprocessed, ShaderError> {
+            let text = stage.read()?;
+            Ok(preprocess(&text, &stage.name(), &self.include_dir, defines)?)
+        };
+        let vertex = stage(&source.vertex)?;
+        let fragment = stage(&source.fragment)?;
+        let geometry = match &source.geometry {
+            Some(geometry) => Some(stage(geometry)?),
+            None => None
+        };
+
+        let includes = vertex.includes.iter()
+            .chain(fragment.includes.iter())
+            .chain(geometry.iter().flat_map(|geometry| geometry.includes.iter()))
+            .cloned()
+            .collect();
+
+        let geometry_source = geometry.as_ref().map(|geometry| geometry.source.as_str());
+        let key = cache::key(&self.display, &[&vertex.source, &fragment.source, geometry_source.unwrap_or("")]);
+        if let Some(dir) = &self.binary_cache {
+            if let Some(program) = cache::load(dir, &self.display, key) {
+                return Ok((program, includes));
+            }
+        }
+
+        let program = Program::from_source(&self.display, &vertex.source, &fragment.source, geometry_source);
+        match program {
+            Ok(program) => {
+                if let Some(dir) = &self.binary_cache {
+                    cache::store(dir, key, &program);
+                }
+                Ok((program, includes))
+            }
+            Err(ProgramCreationError::CompilationError(log, shader)) => {
+                let log = match (shader, &geometry) {
+                    (ShaderType::Vertex, _) => vertex.remap_log(&log),
+                    (ShaderType::Fragment, _) => fragment.remap_log(&log),
+                    (ShaderType::Geometry, Some(geometry)) => geometry.remap_log(&log),
+                    _ => log
+                };
+                Err(ShaderError::from_log(shader, &log))
+            }
+            Err(other) => Err(other.into())
+        }
+    }
+
+    /// Reads and compiles GLSL sources at runtime and registers the program under `name`,
+    /// replacing any previous program with this name. The files are watched for changes,
+    /// see `reload_changed`.
+    pub fn load<N, P>(&mut self, name: N, vertex: P, fragment: P, geometry: Option<P>)
+        -> Result<Rc<Box<Program>>, ShaderError> where N: Into<String>, P: AsRef<Path> {
+
+        let source = ShaderSource {
+            vertex: Stage::File(vertex.as_ref().to_path_buf()),
+            fragment: Stage::File(fragment.as_ref().to_path_buf()),
+            geometry: geometry.map(|path| Stage::File(path.as_ref().to_path_buf())),
+            includes: Vec::new(),
+            modified: None
+        };
+        self.register(name.into(), source)
+    }
+
+    /// Permutation of a registered shader compiled with extra `#define`s, e.g.
+    /// `shaders.variant("textured", &["USE_NORMAL"])`. Every permutation is compiled once.
+    pub fn variant<N>(&mut self, name: N, defines: &[&str]) -> Result<Rc<Box<Program>>, ShaderError> where N: AsRef<str> {
+        let name = name.as_ref();
+        if let Some(program) = self.try_variant(name, defines) {
+            return Ok(program);
+        }
+
+        let mut defines = defines.to_vec();
+        defines.sort();
+        let key = variant_key(name, &defines);
+
+        let source = self.sources.get(name).ok_or_else(|| ShaderError::Missing(name.into()))?;
+        let (program, _) = self.build(source, &defines).map_err(|e| self.report(e))?;
+        let program = Rc::new(Box::new(program));
+        self.variants.insert(key, Variant {
+            shader: name.into(),
+            defines: defines.iter().map(|define| define.to_string()).collect(),
+            program: program.clone()
+        });
+        Ok(program)
+    }
+
+    /// Recompiles the shaders whose files changed since they were compiled, returns their names.
+    /// A shader that fails to compile keeps its previous program and its error is put in `errors`.
+    /// Called by the window loop before every frame when `hot_reload` is set.
+    pub fn reload_changed(&mut self) -> Vec<String> {
+        let mut reloaded = Vec::new();
+        if !self.hot_reload {
+            return reloaded;
+        }
+
+        let changed = self.sources.iter()
+            .filter(|(_, source)| source.modified() > source.modified)
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+
+        for name in changed {
+            let source = &self.sources[&name];
+            let modified = source.modified();
+
+            match self.build(source, &[]) {
+                Ok((program, includes)) => {
+                    self.programs.insert(name.clone(), Rc::new(Box::new(program)));
+                    self.errors.remove(&name);
+                    reloaded.push(name.clone());
+                    let source = self.sources.get_mut(&name).unwrap();
+                    source.includes = includes;
+                    source.modified = source.modified();
+                    self.reload_variants(&name);
+                }
+                Err(e) => {
+                    self.errors.insert(name.clone(), e.report());
+                    self.sources.get_mut(&name).unwrap().modified = modified;
+                }
+            }
         }
+        reloaded
+    }
+
+    /// Recompiles the cached permutations of a shader, keeping the old programs on errors.
+    /// Errors are put in `errors` under the key of the variant, e.g. `font[SDF]`.
+    fn reload_variants(&mut self, name: &str) {
+        let source = &self.sources[name];
+        let mut results = Vec::new();
+        for (key, variant) in self.variants.iter().filter(|(_, variant)| variant.shader == name) {
+            let defines = variant.defines.iter().map(|define| define.as_str()).collect::<Vec<_>>();
+            results.push((key.clone(), self.build(source, &defines)));
+        }
+        for (key, result) in results {
+            match result {
+                Ok((program, _)) => {
+                    self.variants.get_mut(&key).unwrap().program = Rc::new(Box::new(program));
+                    self.errors.remove(&key);
+                }
+                Err(e) => {
+                    self.errors.insert(key, e.report());
+                }
+            }
+        }
+    }
+
+    pub fn get<N>(&self, name: N) -> Rc<Box<Program>> where N: AsRef<str> {
+        self.try_get(name.as_ref()).expect(&format!("Shader is missing: {}", name.as_ref()))
+    }
+
+    pub fn try_get<N>(&self, name: N) -> Option<Rc<Box<Program>>> where N: AsRef<str> {
+        self.programs.get(name.as_ref()).cloned()
+    }
+
+    /// Permutation compiled earlier by `variant`, without compiling it.
+    pub fn try_variant<N>(&self, name: N, defines: &[&str]) -> Option<Rc<Box<Program>>> where N: AsRef<str> {
+        let mut defines = defines.to_vec();
+        defines.sort();
+        self.variants.get(&variant_key(name.as_ref(), &defines)).map(|variant| variant.program.clone())
     }
 
     pub fn font(&self) -> Rc<Box<Program>> {
-        self.programs.get("font".into()).cloned().expect("Font shader is missing")
+        self.get("font")
     }
 
     pub fn default(&self) -> Rc<Box<Program>> {
-        self.programs.get("default".into()).cloned().expect("Default shader is missing")
+        self.get("default")
     }
 
     pub fn textured(&self) -> Rc<Box<Program>> {
-        self.programs.get("textured".into()).cloned().expect("Textured shader is missing")
+        self.get("textured")
+    }
+}
+
+/// Location and text of one line of a driver compilation log.
+#[derive(Clone, Debug)]
+pub struct ShaderErrorEntry {
+    /// Source file, or the driver's source string number (usually `"0"`) when the
+    /// source didn't go through the preprocessor.
+    pub file: String,
+    pub line: Option<u32>,
+    pub message: String
+}
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Io(io::Error),
+    Compilation(ShaderType, Vec<ShaderErrorEntry>),
+    Linking(String),
+    Creation(ProgramCreationError),
+    /// Variant of a shader that was never registered.
+    Missing(String)
+}
+
+impl ShaderError {
+    /// Splits a driver log into entries, understands the `file:line:` (Mesa, AMD, Intel)
+    /// and `file(line)` (NVIDIA) forms.
+    pub fn from_log(stage: ShaderType, log: &str) -> ShaderError {
+        let entries = log.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(parse_log_line)
+            .collect();
+        ShaderError::Compilation(stage, entries)
+    }
+
+    /// Entries of a compilation error, empty for other errors.
+    pub fn entries(&self) -> &[ShaderErrorEntry] {
+        match self {
+            ShaderError::Compilation(_, entries) => entries,
+            _ => &[]
+        }
+    }
+
+    /// Human readable description, one entry per line.
+    pub fn report(&self) -> String {
+        match self {
+            ShaderError::Compilation(stage, entries) => {
+                let mut report = format!("Error compiling {:?} shader:", stage);
+                for entry in entries {
+                    report.push('\n');
+                    match entry.line {
+                        Some(line) => report.push_str(&format!("{}:{}: {}", entry.file, line, entry.message)),
+                        None => report.push_str(&entry.message)
+                    }
+                }
+                report
+            }
+            ShaderError::Linking(log) => format!("Error linking shader:\n{}", log),
+            ShaderError::Missing(name) => format!("Shader is missing: {}", name),
+            other => format!("{:?}", other)
+        }
+    }
+
+    /// Shows the error in a blocking popup, or prints it where no popup can be shown.
+    pub fn show_popup(&self) {
+        let report = self.report();
+        if msgbox::create("Shader compilation error", &report, IconType::Error).is_err() {
+            eprintln!("{}", report);
+        }
     }
-}
\ No newline at end of file
+}
+
+/// Key of a permutation in `variants`, `defines` must be sorted.
+fn variant_key(name: &str, defines: &[&str]) -> String {
+    format!("{}[{}]", name, defines.join(","))
+}
+
+fn parse_log_line(line: &str) -> ShaderErrorEntry {
+    let text = ["ERROR:", "WARNING:"].iter()
+        .find_map(|prefix| line.strip_prefix(prefix))
+        .unwrap_or(line)
+        .trim_start();
+    let token = text.split_whitespace().next().unwrap_or("");
+
+    for (index, delimiter) in token.char_indices().filter(|(_, c)| *c == ':' || *c == '(') {
+        let digits = token[index + 1 ..].chars().take_while(|c| c.is_ascii_digit()).count();
+        if index == 0 || digits == 0 {
+            continue;
+        }
+        let line_number = token[index + 1 .. index + 1 + digits].parse().ok();
+        let mut rest = &text[index + 1 + digits ..];
+        if delimiter == '(' {
+            rest = rest.strip_prefix(')').unwrap_or(rest);
+        }
+        // column number, e.g. Mesa's `0:12(5):`
+        if let Some(column) = rest.strip_prefix('(') {
+            rest = column.split_once(')').map(|(_, rest)| rest).unwrap_or(column);
+        }
+        return ShaderErrorEntry {
+            file: token[.. index].into(),
+            line: line_number,
+            message: rest.trim_start_matches(|c: char| c == ':' || c.is_whitespace()).into()
+        };
+    }
+
+    ShaderErrorEntry {
+        file: String::new(),
+        line: None,
+        message: line.into()
+    }
+}
+
+impl From<io::Error> for ShaderError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<ProgramCreationError> for ShaderError {
+    fn from(e: ProgramCreationError) -> Self {
+        match e {
+            ProgramCreationError::CompilationError(log, stage) => Self::from_log(stage, &log),
+            ProgramCreationError::LinkingError(log) => Self::Linking(log),
+            other => Self::Creation(other)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mesa_log_line() {
+        let entry = parse_log_line("0:12(5): error: `x' undeclared");
+        assert_eq!(entry.file, "0");
+        assert_eq!(entry.line, Some(12));
+        assert_eq!(entry.message, "error: `x' undeclared");
+    }
+
+    #[test]
+    fn nvidia_log_line() {
+        let entry = parse_log_line("0(12) : error C0000: syntax error, unexpected '}'");
+        assert_eq!(entry.file, "0");
+        assert_eq!(entry.line, Some(12));
+        assert_eq!(entry.message, "error C0000: syntax error, unexpected '}'");
+    }
+
+    #[test]
+    fn prefixed_log_line() {
+        let entry = parse_log_line("ERROR: font.fsh:3: 'x' : undeclared identifier");
+        assert_eq!(entry.file, "font.fsh");
+        assert_eq!(entry.line, Some(3));
+        assert_eq!(entry.message, "'x' : undeclared identifier");
+    }
+
+    #[test]
+    fn unknown_log_line() {
+        let entry = parse_log_line("Compilation failed.");
+        assert_eq!(entry.file, "");
+        assert_eq!(entry.line, None);
+        assert_eq!(entry.message, "Compilation failed.");
+    }
+
+    #[test]
+    fn log_skips_blank_lines() {
+        let error = ShaderError::from_log(ShaderType::Fragment, "0:1(1): error: a\n\n  \n0:2(1): error: b\n");
+        let lines = error.entries().iter().map(|entry| entry.line).collect::<Vec<_>>();
+        assert_eq!(lines, vec![Some(1), Some(2)]);
+    }
+}
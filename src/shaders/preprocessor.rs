@@ -0,0 +1,127 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// GLSL source with `#include`s resolved and `#define`s injected, along with the origin
+/// of every line to translate driver error messages back to the original files.
+pub struct Preprocessed {
+    pub source: String,
+    /// Files read through `#include`.
+    pub includes: Vec<PathBuf>,
+    files: Vec<String>,
+    /// File index and line number for each line of `source`.
+    lines: Vec<(usize, u32)>
+}
+
+/// Resolves `#include "file"` directives relative to `include_dir`, every file is included
+/// at most once. `defines` are inserted right after the `#version` directive, either as
+/// `NAME` or `NAME VALUE`.
+pub fn preprocess(source: &str, file: &str, include_dir: &Path, defines: &[&str]) -> io::Result<Preprocessed> {
+    let mut output = Preprocessed {
+        source: String::new(),
+        includes: Vec::new(),
+        files: Vec::new(),
+        lines: Vec::new()
+    };
+    let mut included = HashSet::new();
+    expand(source, file, include_dir, &mut output, &mut included, &mut Some(defines))?;
+    Ok(output)
+}
+
+fn expand(source: &str, file: &str, include_dir: &Path, output: &mut Preprocessed,
+          included: &mut HashSet<PathBuf>, defines: &mut Option<&[&str]>) -> io::Result<()> {
+
+    let file_index = output.files.len();
+    output.files.push(file.into());
+
+    let has_version = source.lines().any(|line| line.trim_start().starts_with("#version"));
+    if !has_version {
+        inject_defines(defines, file_index, 0, output);
+    }
+
+    for (number, line) in source.lines().enumerate() {
+        let number = number as u32 + 1;
+        let directive = line.trim_start();
+
+        if let Some(rest) = directive.strip_prefix("#include") {
+            let name = rest.trim().trim_matches(|c| c == '"' || c == '<' || c == '>');
+            let path = include_dir.join(name);
+            if included.insert(path.clone()) {
+                let contents = fs::read_to_string(&path).map_err(|e| {
+                    io::Error::new(e.kind(), format!("{}:{}: can't include {}: {}", file, number, path.display(), e))
+                })?;
+                output.includes.push(path);
+                expand(&contents, name, include_dir, output, included, defines)?;
+            }
+            continue;
+        }
+
+        output.source.push_str(line);
+        output.source.push('\n');
+        output.lines.push((file_index, number));
+
+        if directive.starts_with("#version") {
+            inject_defines(defines, file_index, number, output);
+        }
+    }
+    Ok(())
+}
+
+/// Defines are only injected once, in the first file.
+fn inject_defines(defines: &mut Option<&[&str]>, file_index: usize, line: u32, output: &mut Preprocessed) {
+    for define in defines.take().unwrap_or(&[]) {
+        output.source.push_str("#define ");
+        output.source.push_str(define);
+        output.source.push('\n');
+        output.lines.push((file_index, line));
+    }
+}
+
+impl Preprocessed {
+    /// File name and line number of a line of the preprocessed source, counting from 1.
+    pub fn origin(&self, line: u32) -> Option<(&str, u32)> {
+        let (file, line) = *self.lines.get((line as usize).checked_sub(1)?)?;
+        Some((&self.files[file], line))
+    }
+
+    /// Replaces the line references of a driver log, like `0(12)` (NVIDIA) or `0:12` (Mesa, AMD),
+    /// with the original file and line.
+    pub fn remap_log(&self, log: &str) -> String {
+        log.lines()
+            .map(|line| self.remap_line(line))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn remap_line(&self, line: &str) -> String {
+        let bytes = line.as_bytes();
+        for start in 0 .. bytes.len() {
+            if bytes[start] != b'0' || (start > 0 && bytes[start - 1].is_ascii_digit()) {
+                continue;
+            }
+            let (open, close) = match bytes.get(start + 1) {
+                Some(b'(') => (start + 2, Some(b')')),
+                Some(b':') => (start + 2, None),
+                _ => continue
+            };
+            let digits = bytes[open ..].iter().take_while(|b| b.is_ascii_digit()).count();
+            if digits == 0 {
+                continue;
+            }
+            let mut end = open + digits;
+            if let Some(close) = close {
+                if bytes.get(end) != Some(&close) {
+                    continue;
+                }
+                end += 1;
+            }
+
+            let number = line[open .. open + digits].parse::<u32>().unwrap_or(0);
+            if let Some((file, original)) = self.origin(number) {
+                return format!("{}{}:{}{}", &line[.. start], file, original, &line[end ..]);
+            }
+        }
+        line.into()
+    }
+}
@@ -0,0 +1,581 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+use glium::{DrawParameters, Program, Surface};
+use serde::Deserialize;
+use crate::render::Canvas;
+use crate::textures::{TextureError, TextureManager, TextureRegion};
+
+/// Frames of a sprite sheet texture together with the animations defined over them.
+#[derive(Clone, Debug)]
+pub struct SpriteSheet {
+    pub frames: Vec<TextureRegion>,
+    pub frame_names: HashMap<String, usize>,
+    pub animations: HashMap<String, Animation>,
+    /// Placement of trimmed frames inside their untrimmed size, as `[x, y, width, height]`
+    /// fractions of it. Frames without an entry cover the whole size.
+    pub trims: HashMap<usize, [f32; 4]>
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PlaybackMode {
+    Once, Loop, PingPong
+}
+
+#[derive(Clone, Debug)]
+pub struct AnimationFrame {
+    /// Index in `SpriteSheet::frames`.
+    pub frame: usize,
+    /// Time the frame stays on screen, in seconds.
+    pub duration: f32,
+    /// Event reported by `AnimatedSprite::update` when the frame is shown.
+    pub event: Option<String>
+}
+
+#[derive(Clone, Debug)]
+pub struct Animation {
+    pub frames: Vec<AnimationFrame>,
+    pub mode: PlaybackMode
+}
+
+impl Animation {
+    pub fn new(mode: PlaybackMode) -> Animation {
+        Animation {
+            frames: Vec::new(),
+            mode
+        }
+    }
+
+    /// Animation over `frames` where every frame lasts for `duration` seconds.
+    pub fn uniform<I>(frames: I, duration: f32, mode: PlaybackMode) -> Animation where I: IntoIterator<Item=usize> {
+        frames.into_iter().fold(Self::new(mode), |animation, frame| animation.frame(frame, duration))
+    }
+
+    pub fn frame(mut self, frame: usize, duration: f32) -> Self {
+        self.frames.push(AnimationFrame { frame, duration, event: None });
+        self
+    }
+
+    /// Attaches an event to the `index`-th frame of the animation.
+    pub fn event<E>(mut self, index: usize, event: E) -> Result<Self, SpriteSheetError> where E: Into<String> {
+        let count = self.frames.len();
+        let frame = self.frames.get_mut(index)
+            .ok_or_else(|| SpriteSheetError::Animation(format!("Frame {} of {}", index, count)))?;
+        frame.event = Some(event.into());
+        Ok(self)
+    }
+
+    /// Total duration of a single pass through the frames.
+    pub fn duration(&self) -> f32 {
+        self.frames.iter().map(|f| f.duration).sum()
+    }
+}
+
+impl SpriteSheet {
+    pub fn new() -> SpriteSheet {
+        SpriteSheet {
+            frames: Vec::new(),
+            frame_names: HashMap::new(),
+            animations: HashMap::new(),
+            trims: HashMap::new()
+        }
+    }
+
+    /// Cuts `texture` into a grid of `frame_size` cells, frames go row by row.
+    pub fn grid<T>(texture: T, texture_size: (u32, u32), frame_size: (u32, u32), spacing: u32)
+        -> Result<SpriteSheet, SpriteSheetError> where T: Into<String> {
+
+        let (w, h) = frame_size;
+        if w == 0 || h == 0 {
+            return Err(SpriteSheetError::Descriptor(format!("Frame size of {}x{}", w, h)));
+        }
+        let texture = texture.into();
+        let mut sheet = Self::new();
+        let columns = (texture_size.0 + spacing) / (w + spacing);
+        let rows = (texture_size.1 + spacing) / (h + spacing);
+
+        for row in 0 .. rows {
+            for column in 0 .. columns {
+                sheet.frames.push(TextureRegion::from_pixels(
+                    texture.clone(), texture_size, column * (w + spacing), row * (h + spacing), w, h
+                ));
+            }
+        }
+        Ok(sheet)
+    }
+
+    /// Loads a grid sprite sheet through `TextureManager`.
+    pub fn load_grid<P>(manager: &mut TextureManager, path: P, frame_size: (u32, u32), spacing: u32)
+        -> Result<SpriteSheet, SpriteSheetError> where P: AsRef<Path> {
+
+        let path = path.as_ref();
+        let name = path.to_string_lossy().into_owned();
+        let texture = manager.try_get_or_load(name.clone(), path)?;
+        Self::grid(name, texture.dimensions(), frame_size, spacing)
+    }
+
+    /// Loads a sprite sheet described by a JSON file in the TexturePacker or Aseprite format
+    /// (both "hash" and "array" flavours). The image is looked up next to the descriptor.
+    /// Aseprite frame tags become animations, using per-frame durations of the export.
+    /// Trimmed frames are placed where they were in the untrimmed frame, see `frame_bounds`.
+    pub fn load_json<P>(manager: &mut TextureManager, path: P) -> Result<SpriteSheet, SpriteSheetError>
+        where P: AsRef<Path> {
+
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)?;
+        let descriptor: SheetDescriptor = serde_json::from_str(&json)?;
+
+        let image = path.parent().unwrap_or(Path::new("")).join(&descriptor.meta.image);
+        let name = image.to_string_lossy().into_owned();
+        let texture = manager.try_get_or_load(name.clone(), &image)?;
+
+        Self::from_descriptor(name, texture.dimensions(), descriptor)
+    }
+
+    /// Parses a JSON descriptor for an already loaded `texture`.
+    pub fn parse_json<T>(texture: T, texture_size: (u32, u32), json: &str) -> Result<SpriteSheet, SpriteSheetError>
+        where T: Into<String> {
+
+        let descriptor: SheetDescriptor = serde_json::from_str(json)?;
+        Self::from_descriptor(texture.into(), texture_size, descriptor)
+    }
+
+    fn from_descriptor(texture: String, texture_size: (u32, u32), descriptor: SheetDescriptor)
+        -> Result<SpriteSheet, SpriteSheetError> {
+
+        // the order of a "hash" is significant, frame tags refer to frames by index
+        let frames: Vec<(String, FrameDescriptor)> = match descriptor.frames {
+            FramesDescriptor::Hash(frames) => frames.into_iter()
+                .map(|(name, frame)| serde_json::from_value(frame).map(|frame| (name, frame)))
+                .collect::<Result<_, _>>()?,
+            FramesDescriptor::Array(frames) => frames.into_iter()
+                .map(|f| (f.filename.clone().unwrap_or_default(), f))
+                .collect()
+        };
+
+        let mut sheet = Self::new();
+        let mut durations = Vec::with_capacity(frames.len());
+
+        for (index, (name, frame)) in frames.into_iter().enumerate() {
+            let FrameRect { x, y, w, h } = frame.frame;
+            let duration = frame.duration.unwrap_or(100);
+            if duration == 0 {
+                return Err(SpriteSheetError::Descriptor(format!("Frame {} has no duration", name)));
+            }
+            sheet.frames.push(TextureRegion::from_pixels(texture.clone(), texture_size, x, y, w, h));
+            if let (true, Some(trim), Some(source)) = (frame.trimmed, frame.sprite_source_size, frame.source_size) {
+                if source.w > 0 && source.h > 0 {
+                    let (sw, sh) = (source.w as f32, source.h as f32);
+                    sheet.trims.insert(index, [
+                        trim.x as f32 / sw, trim.y as f32 / sh, trim.w as f32 / sw, trim.h as f32 / sh
+                    ]);
+                }
+            }
+            sheet.frame_names.insert(name, index);
+            durations.push(duration as f32 / 1000.0);
+        }
+
+        for tag in descriptor.meta.frame_tags {
+            if tag.from > tag.to || tag.to >= durations.len() {
+                return Err(SpriteSheetError::Descriptor(format!(
+                    "Frame tag {} refers to frames {}..={} of {}", tag.name, tag.from, tag.to, durations.len()
+                )));
+            }
+            let mut indices: Vec<usize> = (tag.from ..= tag.to).collect();
+            let mode = match tag.direction.as_str() {
+                "pingpong" => PlaybackMode::PingPong,
+                "reverse" => {
+                    indices.reverse();
+                    PlaybackMode::Loop
+                }
+                _ => PlaybackMode::Loop
+            };
+            let animation = indices.into_iter()
+                .fold(Animation::new(mode), |animation, frame| animation.frame(frame, durations[frame]));
+            sheet.animations.insert(tag.name, animation);
+        }
+
+        Ok(sheet)
+    }
+
+    pub fn add_animation<N>(&mut self, name: N, animation: Animation) where N: Into<String> {
+        self.animations.insert(name.into(), animation);
+    }
+
+    pub fn frame<N>(&self, name: N) -> Option<&TextureRegion> where N: AsRef<str> {
+        self.frame_names.get(name.as_ref()).map(|&index| &self.frames[index])
+    }
+
+    /// Where to draw the `index`-th frame so that `bounds` covers its untrimmed size.
+    pub fn frame_bounds(&self, index: usize, bounds: [f32; 4]) -> [f32; 4] {
+        let [x, y, w, h] = bounds;
+        match self.trims.get(&index) {
+            Some([tx, ty, tw, th]) => [x + tx * w, y + ty * h, tw * w, th * h],
+            None => bounds
+        }
+    }
+}
+
+/// Plays animations of a `SpriteSheet`.
+///
+/// ```ignore
+/// for event in sprite.update(time_elapsed) {
+///     println!("{}", event);
+/// }
+/// sprite.draw(canvas, [x, y, 64.0, 64.0], [1.0; 4], &*shader, &params);
+/// ```
+pub struct AnimatedSprite {
+    sheet: Rc<SpriteSheet>,
+    animation: String,
+    position: usize,
+    elapsed: f32,
+    forward: bool,
+    finished: bool,
+    started: bool,
+    pub speed: f32
+}
+
+impl AnimatedSprite {
+    pub fn new<N>(sheet: Rc<SpriteSheet>, animation: N) -> Result<AnimatedSprite, SpriteSheetError>
+        where N: Into<String> {
+
+        let animation = animation.into();
+        if !sheet.animations.contains_key(&animation) {
+            return Err(SpriteSheetError::Animation(animation));
+        }
+        Ok(AnimatedSprite {
+            sheet, animation,
+            position: 0,
+            elapsed: 0.0,
+            forward: true,
+            finished: false,
+            started: false,
+            speed: 1.0
+        })
+    }
+
+    pub fn sheet(&self) -> Rc<SpriteSheet> {
+        self.sheet.clone()
+    }
+
+    pub fn animation_name(&self) -> &str {
+        &self.animation
+    }
+
+    /// Switches to another animation; does nothing if it is already playing.
+    pub fn play<N>(&mut self, animation: N) -> Result<(), SpriteSheetError> where N: AsRef<str> {
+        let animation = animation.as_ref();
+        if self.animation != animation {
+            if !self.sheet.animations.contains_key(animation) {
+                return Err(SpriteSheetError::Animation(animation.into()));
+            }
+            self.animation = animation.into();
+            self.restart();
+        }
+        Ok(())
+    }
+
+    pub fn restart(&mut self) {
+        self.position = 0;
+        self.elapsed = 0.0;
+        self.forward = true;
+        self.finished = false;
+        self.started = false;
+    }
+
+    /// Whether an animation in `PlaybackMode::Once` has reached its last frame.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Advances the animation by `time_elapsed` seconds and returns the events
+    /// of the frames that were shown meanwhile.
+    pub fn update(&mut self, time_elapsed: f32) -> Vec<String> {
+        let sheet = self.sheet.clone();
+        let animation = &sheet.animations[&self.animation];
+        let mut events = Vec::new();
+
+        if animation.frames.is_empty() {
+            return events;
+        }
+
+        if !self.started {
+            self.started = true;
+            events.extend(animation.frames[self.position].event.clone());
+        }
+
+        self.elapsed += time_elapsed * self.speed;
+
+        while !self.finished {
+            let duration = animation.frames[self.position].duration;
+            if duration <= 0.0 || self.elapsed < duration {
+                break;
+            }
+            self.elapsed -= duration;
+
+            if self.advance(animation) {
+                events.extend(animation.frames[self.position].event.clone());
+            } else {
+                self.elapsed = 0.0;
+                self.finished = true;
+            }
+        }
+        events
+    }
+
+    fn advance(&mut self, animation: &Animation) -> bool {
+        let last = animation.frames.len() - 1;
+        match animation.mode {
+            PlaybackMode::Once => {
+                if self.position < last {
+                    self.position += 1;
+                    true
+                } else {
+                    false
+                }
+            }
+            PlaybackMode::Loop => {
+                self.position = if self.position < last { self.position + 1 } else { 0 };
+                true
+            }
+            PlaybackMode::PingPong => {
+                if last > 0 {
+                    if self.forward && self.position == last {
+                        self.forward = false;
+                    } else if !self.forward && self.position == 0 {
+                        self.forward = true;
+                    }
+                    if self.forward {
+                        self.position += 1;
+                    } else {
+                        self.position -= 1;
+                    }
+                }
+                true
+            }
+        }
+    }
+
+    /// Index in `SpriteSheet::frames` of the frame that is currently shown.
+    pub fn current_index(&self) -> Option<usize> {
+        let animation = &self.sheet.animations[&self.animation];
+        animation.frames.get(self.position).map(|frame| frame.frame)
+    }
+
+    /// Sprite sheet frame that is currently shown.
+    pub fn current_frame(&self) -> Option<&TextureRegion> {
+        self.sheet.frames.get(self.current_index()?)
+    }
+
+    /// Draws the current frame, `bounds` covers its untrimmed size.
+    pub fn draw<S, B, C>(&self, canvas: &mut Canvas<S>, bounds: B, color: C, program: &Program,
+                         params: &DrawParameters)
+        where S: Surface, B: Into<[f32; 4]>, C: Into<[f32; 4]> {
+
+        if let (Some(index), Some(region)) = (self.current_index(), self.current_frame()) {
+            let bounds = self.sheet.frame_bounds(index, bounds.into());
+            canvas.fill_texture_region(region, bounds, color, program, params);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SheetDescriptor {
+    frames: FramesDescriptor,
+    meta: MetaDescriptor
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FramesDescriptor {
+    Hash(serde_json::Map<String, serde_json::Value>),
+    Array(Vec<FrameDescriptor>)
+}
+
+#[derive(Deserialize)]
+struct FrameDescriptor {
+    filename: Option<String>,
+    frame: FrameRect,
+    duration: Option<u32>,
+    #[serde(default)]
+    trimmed: bool,
+    // placement of `frame` in the untrimmed frame
+    #[serde(rename = "spriteSourceSize")]
+    sprite_source_size: Option<FrameRect>,
+    #[serde(rename = "sourceSize")]
+    source_size: Option<SourceSize>
+}
+
+#[derive(Deserialize)]
+struct FrameRect {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32
+}
+
+#[derive(Deserialize)]
+struct SourceSize {
+    w: u32,
+    h: u32
+}
+
+#[derive(Deserialize)]
+struct MetaDescriptor {
+    image: String,
+    #[serde(rename = "frameTags", default)]
+    frame_tags: Vec<FrameTag>
+}
+
+#[derive(Deserialize)]
+struct FrameTag {
+    name: String,
+    from: usize,
+    to: usize,
+    #[serde(default)]
+    direction: String
+}
+
+#[derive(Debug)]
+pub enum SpriteSheetError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Texture(TextureError),
+    /// Frames or frame tags that can't be played, e.g. a tag past the last frame.
+    Descriptor(String),
+    /// Animation that isn't in the sheet, or a frame past the end of an animation.
+    Animation(String)
+}
+
+impl From<std::io::Error> for SpriteSheetError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SpriteSheetError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+impl From<TextureError> for SpriteSheetError {
+    fn from(e: TextureError) -> Self {
+        Self::Texture(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(animation: Animation) -> AnimatedSprite {
+        let mut sheet = SpriteSheet::new();
+        sheet.add_animation("walk", animation);
+        AnimatedSprite::new(Rc::new(sheet), "walk").unwrap()
+    }
+
+    fn positions(sprite: &mut AnimatedSprite, steps: usize, time_elapsed: f32) -> Vec<usize> {
+        (0 .. steps)
+            .map(|_| {
+                sprite.update(time_elapsed);
+                sprite.position
+            })
+            .collect()
+    }
+
+    #[test]
+    fn loop_wraps_around() {
+        let mut sprite = player(Animation::uniform(0 .. 3, 0.25, PlaybackMode::Loop));
+        assert_eq!(positions(&mut sprite, 4, 0.25), vec![1, 2, 0, 1]);
+        assert!(!sprite.is_finished());
+    }
+
+    #[test]
+    fn ping_pong_turns_at_both_ends() {
+        let mut sprite = player(Animation::uniform(0 .. 3, 0.25, PlaybackMode::PingPong));
+        assert_eq!(positions(&mut sprite, 6, 0.25), vec![1, 2, 1, 0, 1, 2]);
+    }
+
+    #[test]
+    fn once_stops_at_the_last_frame() {
+        let mut sprite = player(Animation::uniform(0 .. 3, 0.25, PlaybackMode::Once));
+        sprite.update(0.5);
+        assert!(!sprite.is_finished());
+        sprite.update(10.0);
+        assert!(sprite.is_finished());
+        assert_eq!(sprite.position, 2);
+        assert!(sprite.update(1.0).is_empty());
+
+        sprite.restart();
+        assert_eq!(sprite.position, 0);
+        assert!(!sprite.is_finished());
+    }
+
+    #[test]
+    fn events_across_large_steps() {
+        let animation = Animation::uniform(0 .. 3, 0.25, PlaybackMode::Loop)
+            .event(0, "a").unwrap()
+            .event(1, "b").unwrap()
+            .event(2, "c").unwrap();
+        let mut sprite = player(animation);
+        assert_eq!(sprite.update(0.0), vec!["a"]);
+        assert_eq!(sprite.update(1.0), vec!["b", "c", "a", "b"]);
+
+        let animation = Animation::uniform(0 .. 2, 0.25, PlaybackMode::Once).event(1, "end").unwrap();
+        let mut once = player(animation);
+        assert_eq!(once.update(5.0), vec!["end"]);
+        assert!(once.is_finished());
+    }
+
+    #[test]
+    fn speed_scales_time() {
+        let mut sprite = player(Animation::uniform(0 .. 4, 0.25, PlaybackMode::Loop));
+        sprite.speed = 2.0;
+        assert_eq!(positions(&mut sprite, 2, 0.25), vec![2, 0]);
+    }
+
+    #[test]
+    fn missing_animations_and_frames() {
+        assert!(Animation::uniform(0 .. 2, 0.1, PlaybackMode::Loop).event(2, "x").is_err());
+        assert!(AnimatedSprite::new(Rc::new(SpriteSheet::new()), "walk").is_err());
+
+        let mut sprite = player(Animation::uniform(0 .. 2, 0.1, PlaybackMode::Loop));
+        assert!(sprite.play("run").is_err());
+        assert_eq!(sprite.animation_name(), "walk");
+        assert!(sprite.play("walk").is_ok());
+    }
+
+    #[test]
+    fn grid_frames() {
+        let sheet = SpriteSheet::grid("sheet", (70, 34), (16, 16), 2).unwrap();
+        assert_eq!(sheet.frames.len(), 8);
+        assert_eq!(sheet.frames[5].size, (16, 16));
+        assert!(SpriteSheet::grid("sheet", (64, 64), (0, 16), 0).is_err());
+    }
+
+    #[test]
+    fn trimmed_frames() {
+        let json = r#"{
+            "frames": [
+                {"filename": "a", "frame": {"x": 0, "y": 0, "w": 8, "h": 4}, "trimmed": true,
+                 "spriteSourceSize": {"x": 4, "y": 8, "w": 8, "h": 4}, "sourceSize": {"w": 16, "h": 16}},
+                {"filename": "b", "frame": {"x": 8, "y": 0, "w": 16, "h": 16}, "trimmed": false,
+                 "spriteSourceSize": {"x": 0, "y": 0, "w": 16, "h": 16}, "sourceSize": {"w": 16, "h": 16}}
+            ],
+            "meta": {"image": "sheet.png"}
+        }"#;
+        let sheet = SpriteSheet::parse_json("sheet", (32, 32), json).unwrap();
+        assert_eq!(sheet.frame_bounds(0, [0.0, 0.0, 32.0, 32.0]), [8.0, 16.0, 16.0, 8.0]);
+        assert_eq!(sheet.frame_bounds(1, [0.0, 0.0, 32.0, 32.0]), [0.0, 0.0, 32.0, 32.0]);
+    }
+
+    #[test]
+    fn descriptor_errors() {
+        let zero = r#"{"frames": [{"frame": {"x": 0, "y": 0, "w": 1, "h": 1}, "duration": 0}], "meta": {"image": "a"}}"#;
+        assert!(matches!(SpriteSheet::parse_json("a", (1, 1), zero), Err(SpriteSheetError::Descriptor(_))));
+        let tag = r#"{"frames": [{"frame": {"x": 0, "y": 0, "w": 1, "h": 1}}],
+                      "meta": {"image": "a", "frameTags": [{"name": "t", "from": 0, "to": 1}]}}"#;
+        assert!(matches!(SpriteSheet::parse_json("a", (1, 1), tag), Err(SpriteSheetError::Descriptor(_))));
+    }
+}
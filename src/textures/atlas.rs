@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::path::Path;
+use image::{GenericImage, ImageError, ImageResult, RgbaImage};
+
+pub const DEFAULT_ATLAS_SIZE: u32 = 2048;
+
+/// Region of a texture registered in `TextureManager`.
+/// Coordinates are in texture units with `(0, 0)` at the top-left corner of the image.
+#[derive(Clone, Debug)]
+pub struct TextureRegion {
+    pub texture: String,
+    pub uv: [f32; 4],
+    pub size: (u32, u32)
+}
+
+impl TextureRegion {
+    /// Region covering the whole texture.
+    pub fn full<T>(texture: T, size: (u32, u32)) -> TextureRegion where T: Into<String> {
+        TextureRegion {
+            texture: texture.into(),
+            uv: [0.0, 0.0, 1.0, 1.0],
+            size
+        }
+    }
+
+    /// Region of `page_size` texture, given in pixels.
+    pub fn from_pixels<T>(texture: T, page_size: (u32, u32), x: u32, y: u32, w: u32, h: u32) -> TextureRegion
+        where T: Into<String> {
+
+        let (pw, ph) = (page_size.0 as f32, page_size.1 as f32);
+        TextureRegion {
+            texture: texture.into(),
+            uv: [x as f32 / pw, y as f32 / ph, w as f32 / pw, h as f32 / ph],
+            size: (w, h)
+        }
+    }
+}
+
+/// Pages and regions produced by `AtlasBuilder`, as registered in `TextureManager`.
+#[derive(Clone, Debug)]
+pub struct TextureAtlas {
+    pub pages: Vec<String>,
+    pub regions: HashMap<String, TextureRegion>
+}
+
+/// Shelf rectangle packer: rectangles are placed left to right in rows ("shelves"),
+/// a new shelf is started when the current one has no room left.
+pub struct RectPacker {
+    width: u32,
+    height: u32,
+    padding: u32,
+    cursor: (u32, u32),
+    shelf_height: u32,
+    used: (u32, u32)
+}
+
+impl RectPacker {
+    pub fn new(width: u32, height: u32, padding: u32) -> RectPacker {
+        RectPacker {
+            width, height, padding,
+            cursor: (0, 0),
+            shelf_height: 0,
+            used: (0, 0)
+        }
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Area that is actually occupied by the packed rectangles.
+    pub fn used(&self) -> (u32, u32) {
+        self.used
+    }
+
+    /// Reserves a `w`x`h` rectangle and returns its top-left corner,
+    /// or `None` if there is no room left.
+    pub fn pack(&mut self, w: u32, h: u32) -> Option<(u32, u32)> {
+        if w > self.width || h > self.height {
+            return None;
+        }
+
+        // carriage return if the rectangle doesn't fit in the current shelf
+        if self.cursor.0 + w > self.width {
+            self.cursor.0 = 0;
+            self.cursor.1 += self.shelf_height;
+            self.shelf_height = 0;
+        }
+
+        if self.cursor.1 + h > self.height {
+            return None;
+        }
+
+        let position = self.cursor;
+        self.cursor.0 += w + self.padding;
+        self.shelf_height = self.shelf_height.max(h + self.padding);
+        self.used.0 = self.used.0.max(position.0 + w);
+        self.used.1 = self.used.1.max(position.1 + h);
+        Some(position)
+    }
+}
+
+/// Packs many images into one or more atlas pages.
+///
+/// ```ignore
+/// let builder = AtlasBuilder::new().padding(2).extrude(1)
+///     .with_dir("resources/textures/ui")?;
+/// textures.load_atlas("ui", builder);
+/// ```
+pub struct AtlasBuilder {
+    max_size: u32,
+    padding: u32,
+    extrude: u32,
+    images: Vec<(String, RgbaImage)>
+}
+
+impl AtlasBuilder {
+    pub fn new() -> AtlasBuilder {
+        AtlasBuilder {
+            max_size: DEFAULT_ATLAS_SIZE,
+            padding: 2,
+            extrude: 0,
+            images: Vec::new()
+        }
+    }
+
+    /// Maximal width and height of a single page.
+    pub fn max_size(mut self, max_size: u32) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Empty pixels between neighbouring regions.
+    pub fn padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Number of times the border pixels of each image are repeated around it,
+    /// to prevent bleeding when sampling with linear filtering.
+    pub fn extrude(mut self, extrude: u32) -> Self {
+        self.extrude = extrude;
+        self
+    }
+
+    pub fn with_image<N>(mut self, name: N, image: RgbaImage) -> Self where N: Into<String> {
+        self.add_image(name, image);
+        self
+    }
+
+    pub fn with_file<P>(mut self, path: P) -> ImageResult<Self> where P: AsRef<Path> {
+        self.add_file(path)?;
+        Ok(self)
+    }
+
+    pub fn with_dir<P>(mut self, path: P) -> ImageResult<Self> where P: AsRef<Path> {
+        self.add_dir(path)?;
+        Ok(self)
+    }
+
+    pub fn add_image<N>(&mut self, name: N, image: RgbaImage) where N: Into<String> {
+        self.images.push((name.into(), image));
+    }
+
+    /// Adds an image file, its region is named after the file stem.
+    pub fn add_file<P>(&mut self, path: P) -> ImageResult<()> where P: AsRef<Path> {
+        let path = path.as_ref();
+        let name = path.file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let image = image::open(path)?;
+        self.add_image(name, image.into_rgba8());
+        Ok(())
+    }
+
+    /// Adds every image file from a directory (non-recursively).
+    pub fn add_dir<P>(&mut self, path: P) -> ImageResult<()> where P: AsRef<Path> {
+        let mut paths = std::fs::read_dir(path)
+            .map_err(ImageError::IoError)?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.is_file() && image::ImageFormat::from_path(p).is_ok())
+            .collect::<Vec<_>>();
+        paths.sort();
+        for path in paths {
+            self.add_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Packs the images, returning the page bitmaps and the regions of every image,
+    /// where `TextureRegion::texture` is the name of the page: `"{name}#{index}"`.
+    pub fn build(self, name: &str) -> (Vec<RgbaImage>, HashMap<String, TextureRegion>) {
+        let AtlasBuilder { max_size, padding, extrude, mut images } = self;
+
+        // tallest images go first, it gives denser shelves
+        images.sort_by(|(_, a), (_, b)| b.height().cmp(&a.height()).then(b.width().cmp(&a.width())));
+
+        let mut pages: Vec<(RectPacker, Vec<(String, RgbaImage, u32, u32)>)> = Vec::new();
+
+        for (image_name, image) in images {
+            let (w, h) = image.dimensions();
+            let (cell_w, cell_h) = (w + extrude * 2, h + extrude * 2);
+
+            let placed = pages.iter_mut()
+                .find_map(|(packer, content)| packer.pack(cell_w, cell_h).map(|p| (p, content)));
+
+            match placed {
+                Some(((x, y), content)) => content.push((image_name, image, x, y)),
+                None => {
+                    // images bigger than a page get a page of their own
+                    let mut packer = RectPacker::new(max_size.max(cell_w), max_size.max(cell_h), padding);
+                    let (x, y) = packer.pack(cell_w, cell_h).expect("Atlas page is too small");
+                    pages.push((packer, vec![(image_name, image, x, y)]));
+                }
+            }
+        }
+
+        let mut bitmaps = Vec::with_capacity(pages.len());
+        let mut regions = HashMap::new();
+
+        for (index, (packer, content)) in pages.into_iter().enumerate() {
+            let page_name = format!("{}#{}", name, index);
+            let (used_w, used_h) = packer.used();
+            let (page_w, page_h) = (get_nearest_po2(used_w).min(packer.dimensions().0),
+                                    get_nearest_po2(used_h).min(packer.dimensions().1));
+            let mut bitmap = RgbaImage::new(page_w, page_h);
+
+            for (image_name, image, x, y) in content {
+                let (w, h) = image.dimensions();
+                blit_extruded(&mut bitmap, &image, x, y, extrude);
+                regions.insert(image_name, TextureRegion::from_pixels(
+                    page_name.clone(), (page_w, page_h), x + extrude, y + extrude, w, h
+                ));
+            }
+            bitmaps.push(bitmap);
+        }
+
+        (bitmaps, regions)
+    }
+}
+
+/// Copies `image` to `(x + extrude, y + extrude)` and repeats its border pixels `extrude` times.
+fn blit_extruded(target: &mut RgbaImage, image: &RgbaImage, x: u32, y: u32, extrude: u32) {
+    let (w, h) = image.dimensions();
+    target.copy_from(image, x + extrude, y + extrude).expect("Atlas region is out of page bounds");
+
+    if extrude == 0 || w == 0 || h == 0 {
+        return;
+    }
+
+    for dy in 0 .. h + extrude * 2 {
+        for dx in 0 .. w + extrude * 2 {
+            let inside_x = dx >= extrude && dx < w + extrude;
+            let inside_y = dy >= extrude && dy < h + extrude;
+            if inside_x && inside_y {
+                continue;
+            }
+            let sx = (dx as i64 - extrude as i64).clamp(0, w as i64 - 1) as u32;
+            let sy = (dy as i64 - extrude as i64).clamp(0, h as i64 - 1) as u32;
+            target.put_pixel(x + dx, y + dy, *image.get_pixel(sx, sy));
+        }
+    }
+}
+
+/// Function that will calculate the nearest power of two.
+fn get_nearest_po2(x: u32) -> u32 {
+    x.max(1).next_power_of_two()
+}
@@ -0,0 +1,899 @@
+use glium::texture::CompressedSrgbFormat;
+use image::{Rgba, RgbaImage};
+use super::TextureError;
+
+const DDS_MAGIC: &[u8] = b"DDS ";
+const KTX1_MAGIC: &[u8] = &[0xAB, 0x4B, 0x54, 0x58, 0x20, 0x31, 0x31, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+const KTX2_MAGIC: &[u8] = &[0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Block compression of the texture payload, every block covers 4x4 pixels.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BlockFormat {
+    Bc1, Bc2, Bc3, Bc4, Bc5, Bc6h, Bc7, Etc2Rgb, Etc2Rgba
+}
+
+impl BlockFormat {
+    pub fn block_bytes(self) -> usize {
+        match self {
+            BlockFormat::Bc1 | BlockFormat::Bc4 | BlockFormat::Etc2Rgb => 8,
+            _ => 16
+        }
+    }
+
+    /// Size in bytes of a `width`x`height` mipmap level.
+    pub fn level_bytes(self, width: u32, height: u32) -> usize {
+        let blocks_x = (width.max(1) as usize + 3) / 4;
+        let blocks_y = (height.max(1) as usize + 3) / 4;
+        blocks_x.saturating_mul(blocks_y).saturating_mul(self.block_bytes())
+    }
+
+    /// Format to upload the payload to the GPU as-is, if glium has one.
+    pub fn gpu_format(self) -> Option<CompressedSrgbFormat> {
+        match self {
+            BlockFormat::Bc1 => Some(CompressedSrgbFormat::S3tcDxt1Alpha),
+            BlockFormat::Bc2 => Some(CompressedSrgbFormat::S3tcDxt3Alpha),
+            BlockFormat::Bc3 => Some(CompressedSrgbFormat::S3tcDxt5Alpha),
+            BlockFormat::Bc7 => Some(CompressedSrgbFormat::Bptc),
+            _ => None
+        }
+    }
+
+    /// Whether `decompress` supports the format.
+    pub fn can_decompress(self) -> bool {
+        self != BlockFormat::Bc6h
+    }
+}
+
+/// Contents of a DDS, KTX or KTX2 file: a block compressed image with its mipmap chain.
+/// Only the first layer/face of arrays and cubemaps is read.
+pub struct CompressedImage {
+    pub format: BlockFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Payload of every mipmap level, the biggest first.
+    pub levels: Vec<Vec<u8>>
+}
+
+impl CompressedImage {
+    /// Whether the bytes look like one of the supported containers.
+    pub fn is_container(bytes: &[u8]) -> bool {
+        bytes.starts_with(DDS_MAGIC) || bytes.starts_with(KTX1_MAGIC) || bytes.starts_with(KTX2_MAGIC)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<CompressedImage, TextureError> {
+        if bytes.starts_with(DDS_MAGIC) {
+            parse_dds(bytes)
+        } else if bytes.starts_with(KTX1_MAGIC) {
+            parse_ktx1(bytes)
+        } else if bytes.starts_with(KTX2_MAGIC) {
+            parse_ktx2(bytes)
+        } else {
+            Err(TextureError::Format("Unknown texture container".into()))
+        }
+    }
+
+    /// Dimensions of the `level`-th mipmap.
+    pub fn level_dimensions(&self, level: usize) -> (u32, u32) {
+        let shift = |size: u32| size.checked_shr(level as u32).unwrap_or(0).max(1);
+        (shift(self.width), shift(self.height))
+    }
+
+    /// Decodes a mipmap level on the CPU, for drivers that lack the format.
+    pub fn decompress(&self, level: usize) -> Result<RgbaImage, TextureError> {
+        let (width, height) = self.level_dimensions(level);
+        let data = self.levels.get(level)
+            .ok_or_else(|| TextureError::Format(format!("Missing mipmap level {}", level)))?;
+
+        let decode: fn(&[u8]) -> [[u8; 4]; 16] = match self.format {
+            BlockFormat::Bc1 => |b| decode_bc1(b, true),
+            BlockFormat::Bc2 => decode_bc2,
+            BlockFormat::Bc3 => decode_bc3,
+            BlockFormat::Bc4 => decode_bc4,
+            BlockFormat::Bc5 => decode_bc5,
+            BlockFormat::Bc7 => decode_bc7,
+            BlockFormat::Etc2Rgb => decode_etc2_rgb,
+            BlockFormat::Etc2Rgba => decode_etc2_rgba,
+            other => return Err(TextureError::Format(format!("{:?} can't be decompressed on the CPU", other)))
+        };
+
+        let block_bytes = self.format.block_bytes();
+        let blocks_x = (width as usize + 3) / 4;
+        let mut image = RgbaImage::new(width, height);
+
+        for (index, block) in data.chunks_exact(block_bytes).enumerate() {
+            let (bx, by) = ((index % blocks_x) as u32 * 4, (index / blocks_x) as u32 * 4);
+            if by >= height {
+                break;
+            }
+            let pixels = decode(block);
+            for (i, pixel) in pixels.iter().enumerate() {
+                let (x, y) = (bx + i as u32 % 4, by + i as u32 / 4);
+                if x < width && y < height {
+                    image.put_pixel(x, y, Rgba(*pixel));
+                }
+            }
+        }
+        Ok(image)
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize, big_endian: bool) -> Result<u32, TextureError> {
+    let raw: [u8; 4] = bytes.get(offset .. offset + 4)
+        .ok_or_else(|| TextureError::Format("Truncated texture header".into()))?
+        .try_into().unwrap();
+    Ok(if big_endian { u32::from_be_bytes(raw) } else { u32::from_le_bytes(raw) })
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, TextureError> {
+    Ok(read_u32(bytes, offset, false)? as u64 | (read_u32(bytes, offset + 4, false)? as u64) << 32)
+}
+
+fn slice(bytes: &[u8], offset: usize, len: usize) -> Result<Vec<u8>, TextureError> {
+    offset.checked_add(len)
+        .and_then(|end| bytes.get(offset .. end))
+        .map(|data| data.to_vec())
+        .ok_or_else(|| TextureError::Format("Truncated texture data".into()))
+}
+
+/// Mipmap count of a header, a full chain goes down to 1x1.
+fn check_mipmaps(mipmaps: u32, width: u32, height: u32) -> Result<u32, TextureError> {
+    let max = 32 - width.max(height).max(1).leading_zeros();
+    if mipmaps > max {
+        return Err(TextureError::Format(format!("{} mipmap levels for a {}x{} texture", mipmaps, width, height)));
+    }
+    Ok(mipmaps)
+}
+
+fn parse_dds(bytes: &[u8]) -> Result<CompressedImage, TextureError> {
+    const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+
+    let flags = read_u32(bytes, 8, false)?;
+    let height = read_u32(bytes, 12, false)?;
+    let width = read_u32(bytes, 16, false)?;
+    let mipmaps = if flags & DDSD_MIPMAPCOUNT != 0 { read_u32(bytes, 28, false)?.max(1) } else { 1 };
+    let mipmaps = check_mipmaps(mipmaps, width, height)?;
+    let four_cc = bytes.get(84 .. 88).ok_or_else(|| TextureError::Format("Truncated DDS header".into()))?;
+
+    let (format, mut offset) = match four_cc {
+        b"DXT1" => (BlockFormat::Bc1, 128),
+        b"DXT2" | b"DXT3" => (BlockFormat::Bc2, 128),
+        b"DXT4" | b"DXT5" => (BlockFormat::Bc3, 128),
+        b"ATI1" | b"BC4U" => (BlockFormat::Bc4, 128),
+        b"ATI2" | b"BC5U" => (BlockFormat::Bc5, 128),
+        b"DX10" => {
+            let format = match read_u32(bytes, 128, false)? {
+                70 ..= 72 => BlockFormat::Bc1,
+                73 ..= 75 => BlockFormat::Bc2,
+                76 ..= 78 => BlockFormat::Bc3,
+                79 ..= 81 => BlockFormat::Bc4,
+                82 ..= 84 => BlockFormat::Bc5,
+                94 ..= 96 => BlockFormat::Bc6h,
+                97 ..= 99 => BlockFormat::Bc7,
+                other => return Err(TextureError::Format(format!("Unsupported DXGI format {}", other)))
+            };
+            (format, 148)
+        }
+        other => return Err(TextureError::Format(format!("Unsupported DDS format {:?}", String::from_utf8_lossy(other))))
+    };
+
+    let mut levels = Vec::with_capacity(mipmaps as usize);
+    for level in 0 .. mipmaps {
+        let len = format.level_bytes(width >> level, height >> level);
+        levels.push(slice(bytes, offset, len)?);
+        offset += len;
+    }
+
+    Ok(CompressedImage { format, width, height, levels })
+}
+
+fn parse_ktx1(bytes: &[u8]) -> Result<CompressedImage, TextureError> {
+    let big_endian = read_u32(bytes, 12, false)? != 0x04030201;
+    let internal_format = read_u32(bytes, 28, big_endian)?;
+    let width = read_u32(bytes, 36, big_endian)?;
+    let height = read_u32(bytes, 40, big_endian)?.max(1);
+    let array_elements = read_u32(bytes, 48, big_endian)?;
+    let faces = read_u32(bytes, 52, big_endian)?.max(1);
+    let mipmaps = check_mipmaps(read_u32(bytes, 56, big_endian)?.max(1), width, height)?;
+    let key_value_bytes = read_u32(bytes, 60, big_endian)?;
+
+    let format = match internal_format {
+        0x83F0 | 0x83F1 | 0x8C4C | 0x8C4D => BlockFormat::Bc1,
+        0x83F2 | 0x8C4E => BlockFormat::Bc2,
+        0x83F3 | 0x8C4F => BlockFormat::Bc3,
+        0x8DBB => BlockFormat::Bc4,
+        0x8DBD => BlockFormat::Bc5,
+        0x8E8E | 0x8E8F => BlockFormat::Bc6h,
+        0x8E8C | 0x8E8D => BlockFormat::Bc7,
+        0x9274 | 0x9275 => BlockFormat::Etc2Rgb,
+        0x9278 | 0x9279 => BlockFormat::Etc2Rgba,
+        other => return Err(TextureError::Format(format!("Unsupported KTX internal format {:#x}", other)))
+    };
+
+    let mut offset = 64 + key_value_bytes as usize;
+    let mut levels = Vec::with_capacity(mipmaps as usize);
+    for level in 0 .. mipmaps {
+        let (w, h) = ((width >> level).max(1), (height >> level).max(1));
+        let image_size = read_u32(bytes, offset, big_endian)? as usize;
+        offset += 4;
+        // the size covers a single face of a cubemap, but every layer and face of an array
+        let level_bytes = format.level_bytes(w, h);
+        let expected = match array_elements {
+            0 => level_bytes,
+            layers => level_bytes.saturating_mul(layers as usize).saturating_mul(faces as usize)
+        };
+        if image_size != expected {
+            return Err(TextureError::Format(format!(
+                "Mipmap level {} has {} bytes instead of {}", level, image_size, expected
+            )));
+        }
+        levels.push(slice(bytes, offset, level_bytes)?);
+        // cubemap faces follow each other, every one padded to 4 bytes
+        offset += match array_elements {
+            0 => ((image_size + 3) & !3) * faces as usize,
+            _ => (image_size + 3) & !3
+        };
+    }
+
+    Ok(CompressedImage { format, width, height, levels })
+}
+
+fn parse_ktx2(bytes: &[u8]) -> Result<CompressedImage, TextureError> {
+    let vk_format = read_u32(bytes, 12, false)?;
+    let width = read_u32(bytes, 20, false)?;
+    let height = read_u32(bytes, 24, false)?.max(1);
+    let mipmaps = check_mipmaps(read_u32(bytes, 40, false)?.max(1), width, height)?;
+    let supercompression = read_u32(bytes, 44, false)?;
+
+    if supercompression != 0 {
+        return Err(TextureError::Format(format!("Unsupported KTX2 supercompression {}", supercompression)));
+    }
+
+    let format = match vk_format {
+        131 ..= 134 => BlockFormat::Bc1,
+        135 | 136 => BlockFormat::Bc2,
+        137 | 138 => BlockFormat::Bc3,
+        139 => BlockFormat::Bc4,
+        141 => BlockFormat::Bc5,
+        143 | 144 => BlockFormat::Bc6h,
+        145 | 146 => BlockFormat::Bc7,
+        147 | 148 => BlockFormat::Etc2Rgb,
+        151 | 152 => BlockFormat::Etc2Rgba,
+        other => return Err(TextureError::Format(format!("Unsupported KTX2 format {}", other)))
+    };
+
+    let mut levels = Vec::with_capacity(mipmaps as usize);
+    for level in 0 .. mipmaps {
+        let (w, h) = ((width >> level).max(1), (height >> level).max(1));
+        let offset = read_u64(bytes, 80 + level as usize * 24)? as usize;
+        // only the first layer/face of the level
+        levels.push(slice(bytes, offset, format.level_bytes(w, h))?);
+    }
+
+    Ok(CompressedImage { format, width, height, levels })
+}
+
+fn rgb565(color: u16) -> [u8; 3] {
+    let r = (color >> 11 & 0x1F) as u8;
+    let g = (color >> 5 & 0x3F) as u8;
+    let b = (color & 0x1F) as u8;
+    [r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2]
+}
+
+fn mix(a: [u8; 3], b: [u8; 3], wa: u32, wb: u32) -> [u8; 4] {
+    let c = |i: usize| ((a[i] as u32 * wa + b[i] as u32 * wb) / (wa + wb)) as u8;
+    [c(0), c(1), c(2), 255]
+}
+
+/// Pixels of a block, row by row.
+fn decode_bc1(block: &[u8], punch_through: bool) -> [[u8; 4]; 16] {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let (a, b) = (rgb565(c0), rgb565(c1));
+
+    let palette = if c0 > c1 || !punch_through {
+        [[a[0], a[1], a[2], 255], [b[0], b[1], b[2], 255], mix(a, b, 2, 1), mix(a, b, 1, 2)]
+    } else {
+        [[a[0], a[1], a[2], 255], [b[0], b[1], b[2], 255], mix(a, b, 1, 1), [0, 0, 0, 0]]
+    };
+
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    let mut pixels = [[0; 4]; 16];
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        *pixel = palette[(indices >> (i * 2) & 3) as usize];
+    }
+    pixels
+}
+
+fn decode_bc2(block: &[u8]) -> [[u8; 4]; 16] {
+    let mut pixels = decode_bc1(&block[8 ..], false);
+    let alpha = u64::from_le_bytes(block[.. 8].try_into().unwrap());
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        let a = (alpha >> (i * 4) & 0xF) as u8;
+        pixel[3] = a << 4 | a;
+    }
+    pixels
+}
+
+/// Interpolated single channel block of BC3 alpha, BC4 and BC5.
+fn decode_bc4_channel(block: &[u8]) -> [u8; 16] {
+    let (a0, a1) = (block[0] as u32, block[1] as u32);
+    let mut palette = [a0, a1, 0, 0, 0, 0, 0, 255];
+    if a0 > a1 {
+        for i in 1 .. 7 {
+            palette[i + 1] = ((7 - i as u32) * a0 + i as u32 * a1) / 7;
+        }
+    } else {
+        for i in 1 .. 5 {
+            palette[i + 1] = ((5 - i as u32) * a0 + i as u32 * a1) / 5;
+        }
+    }
+
+    let mut bits = [0u8; 8];
+    bits[.. 6].copy_from_slice(&block[2 .. 8]);
+    let indices = u64::from_le_bytes(bits);
+
+    let mut values = [0; 16];
+    for (i, value) in values.iter_mut().enumerate() {
+        *value = palette[(indices >> (i * 3) & 7) as usize] as u8;
+    }
+    values
+}
+
+fn decode_bc3(block: &[u8]) -> [[u8; 4]; 16] {
+    let mut pixels = decode_bc1(&block[8 ..], false);
+    let alpha = decode_bc4_channel(&block[.. 8]);
+    for (pixel, a) in pixels.iter_mut().zip(alpha.iter()) {
+        pixel[3] = *a;
+    }
+    pixels
+}
+
+fn decode_bc4(block: &[u8]) -> [[u8; 4]; 16] {
+    let red = decode_bc4_channel(block);
+    let mut pixels = [[0; 4]; 16];
+    for (pixel, r) in pixels.iter_mut().zip(red.iter()) {
+        *pixel = [*r, *r, *r, 255];
+    }
+    pixels
+}
+
+fn decode_bc5(block: &[u8]) -> [[u8; 4]; 16] {
+    let red = decode_bc4_channel(&block[.. 8]);
+    let green = decode_bc4_channel(&block[8 ..]);
+    let mut pixels = [[0; 4]; 16];
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        *pixel = [red[i], green[i], 0, 255];
+    }
+    pixels
+}
+
+/// Layout of a BC7 mode: subsets, bits of the partition, rotation and index selection fields,
+/// bits of the color and alpha endpoints, p-bits per endpoint or per subset, and index bits.
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_pbits: bool,
+    shared_pbits: bool,
+    index_bits: u32,
+    secondary_index_bits: u32
+}
+
+const fn bc7_mode(subsets: usize, partition_bits: u32, rotation_bits: u32, selection_bits: u32, color_bits: u32,
+                  alpha_bits: u32, endpoint_pbits: bool, shared_pbits: bool, index_bits: u32,
+                  secondary_index_bits: u32) -> Bc7Mode {
+    Bc7Mode {
+        subsets, partition_bits, rotation_bits, selection_bits, color_bits, alpha_bits,
+        endpoint_pbits, shared_pbits, index_bits, secondary_index_bits
+    }
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    bc7_mode(3, 4, 0, 0, 4, 0, true, false, 3, 0),
+    bc7_mode(2, 6, 0, 0, 6, 0, false, true, 3, 0),
+    bc7_mode(3, 6, 0, 0, 5, 0, false, false, 2, 0),
+    bc7_mode(2, 6, 0, 0, 7, 0, true, false, 2, 0),
+    bc7_mode(1, 0, 2, 1, 5, 6, false, false, 2, 3),
+    bc7_mode(1, 0, 2, 0, 7, 8, false, false, 2, 2),
+    bc7_mode(1, 0, 0, 0, 7, 7, true, false, 4, 0),
+    bc7_mode(2, 6, 0, 0, 5, 5, true, false, 2, 0)
+];
+
+const BC7_WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+const BC7_WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const BC7_WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+/// Two subset partitions, bit `i` is the subset of the `i`-th pixel.
+const BC7_PARTITIONS_2: [u16; 64] = [
+    0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80,
+    0xC800, 0xFFEC, 0xFE80, 0xE800, 0xFFE8, 0xFF00, 0xFFF0, 0xF000,
+    0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE,
+    0x088C, 0x3110, 0x6666, 0x366C, 0x17E8, 0x0FF0, 0x718E, 0x399C,
+    0xAAAA, 0xF0F0, 0x5A5A, 0x33CC, 0x3C3C, 0x55AA, 0x9696, 0xA55A,
+    0x73CE, 0x13C8, 0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660,
+    0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C,
+    0x9336, 0x9CC6, 0x817E, 0xE718, 0xCCF0, 0x0FCC, 0x7744, 0xEE22
+];
+
+/// Three subset partitions, the subset of every pixel row by row.
+const BC7_PARTITIONS_3: [[u8; 16]; 64] = [
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 1, 2, 2, 2, 2], [0, 0, 0, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 2, 0, 0, 1, 2, 2, 1, 1, 2, 2, 1, 1], [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 1, 0, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2], [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1], [0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2], [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2], [0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2],
+    [0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2], [0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2, 1, 2, 2, 2], [0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0, 2, 2, 2, 0],
+    [0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2], [0, 1, 1, 1, 0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0],
+    [0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2], [0, 0, 2, 2, 0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2, 0, 2, 2, 2], [0, 0, 0, 1, 0, 0, 0, 1, 2, 2, 2, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2], [0, 0, 0, 0, 1, 1, 0, 0, 2, 2, 1, 0, 2, 2, 1, 0],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1, 0, 0, 0, 0], [0, 0, 1, 2, 0, 0, 1, 2, 1, 1, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1, 0, 1, 1, 0], [0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1],
+    [0, 0, 2, 2, 1, 1, 0, 2, 1, 1, 0, 2, 0, 0, 2, 2], [0, 1, 1, 0, 0, 1, 1, 0, 2, 0, 0, 2, 2, 2, 2, 2],
+    [0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1], [0, 0, 0, 0, 2, 0, 0, 0, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 2, 2, 2], [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 2, 2, 0, 2, 2, 2], [0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0], [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0],
+    [0, 1, 2, 0, 2, 0, 1, 2, 1, 2, 0, 1, 0, 1, 2, 0], [0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0, 1, 1], [0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1], [0, 0, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 1, 1], [0, 2, 2, 0, 1, 2, 2, 1, 0, 2, 2, 0, 1, 2, 2, 1],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 0, 1, 0, 1], [0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2], [0, 2, 2, 2, 0, 1, 1, 1, 0, 2, 2, 2, 0, 1, 1, 1],
+    [0, 0, 0, 2, 1, 1, 1, 2, 0, 0, 0, 2, 1, 1, 1, 2], [0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2], [0, 0, 0, 2, 1, 1, 1, 2, 1, 1, 1, 2, 0, 0, 0, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2], [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2, 2, 2, 2, 2], [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2],
+    [0, 0, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2], [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2],
+    [0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1], [0, 2, 2, 2, 1, 2, 2, 2, 0, 2, 2, 2, 1, 2, 2, 2],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2], [0, 1, 1, 1, 2, 0, 1, 1, 2, 2, 0, 1, 2, 2, 2, 0]
+];
+
+/// Pixel of the second subset of two subset partitions whose index has one bit less.
+const BC7_ANCHORS_2: [usize; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
+    15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2,
+    15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6,
+    6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15
+];
+
+/// Anchor pixels of the second and third subsets of three subset partitions.
+const BC7_ANCHORS_3: [[usize; 64]; 2] = [
+    [
+        3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3,
+        3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6, 8, 5, 15, 15,
+        8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15,
+        3, 15, 5, 5, 5, 8, 5, 10, 5, 10, 8, 13, 15, 12, 3, 3
+    ],
+    [
+        15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8,
+        15, 8, 15, 3, 15, 8, 15, 8, 3, 15, 6, 10, 15, 15, 10, 8,
+        15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8,
+        15, 3, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8
+    ]
+];
+
+/// Subset of a pixel and whether it is the anchor of its subset.
+fn bc7_subset(subsets: usize, partition: usize, pixel: usize) -> (usize, bool) {
+    let subset = match subsets {
+        2 => (BC7_PARTITIONS_2[partition] >> pixel & 1) as usize,
+        3 => BC7_PARTITIONS_3[partition][pixel] as usize,
+        _ => 0
+    };
+    let anchor = match (subsets, subset) {
+        (_, 0) => 0,
+        (2, _) => BC7_ANCHORS_2[partition],
+        (_, subset) => BC7_ANCHORS_3[subset - 1][partition]
+    };
+    (subset, pixel == anchor)
+}
+
+/// Reads fields of a block from the least significant bit up.
+struct BitReader {
+    bits: u128,
+    position: u32
+}
+
+impl BitReader {
+    fn read(&mut self, count: u32) -> u32 {
+        let value = self.bits.checked_shr(self.position).unwrap_or(0) as u32 & ((1u64 << count) - 1) as u32;
+        self.position += count;
+        value
+    }
+}
+
+/// Endpoint of `bits` bits widened to 8 bits by repeating the high bits.
+fn bc7_expand(value: u32, bits: u32) -> u32 {
+    let value = value << (8 - bits);
+    value | value >> bits
+}
+
+fn decode_bc7(block: &[u8]) -> [[u8; 4]; 16] {
+    let mut bits = BitReader { bits: u128::from_le_bytes(block[.. 16].try_into().unwrap()), position: 0 };
+    // reserved mode, decoded as transparent black
+    let mode = match (0 .. 8).find(|_| bits.read(1) == 1) {
+        Some(mode) => &BC7_MODES[mode],
+        None => return [[0; 4]; 16]
+    };
+
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let selection = bits.read(mode.selection_bits);
+
+    // every channel of the first then second endpoint of each subset
+    let mut endpoints = [[[0u32; 4]; 2]; 3];
+    let channels = if mode.alpha_bits > 0 { 4 } else { 3 };
+    for channel in 0 .. channels {
+        let channel_bits = if channel < 3 { mode.color_bits } else { mode.alpha_bits };
+        for subset in endpoints.iter_mut().take(mode.subsets) {
+            for endpoint in subset.iter_mut() {
+                endpoint[channel] = bits.read(channel_bits);
+            }
+        }
+    }
+
+    let (mut color_bits, mut alpha_bits) = (mode.color_bits, mode.alpha_bits);
+    if mode.endpoint_pbits || mode.shared_pbits {
+        for subset in endpoints.iter_mut().take(mode.subsets) {
+            let shared = if mode.shared_pbits { bits.read(1) } else { 0 };
+            for endpoint in subset.iter_mut() {
+                let pbit = if mode.endpoint_pbits { bits.read(1) } else { shared };
+                for value in endpoint.iter_mut().take(channels) {
+                    *value = *value << 1 | pbit;
+                }
+            }
+        }
+        color_bits += 1;
+        if alpha_bits > 0 {
+            alpha_bits += 1;
+        }
+    }
+    for endpoint in endpoints.iter_mut().flatten() {
+        for value in endpoint.iter_mut().take(3) {
+            *value = bc7_expand(*value, color_bits);
+        }
+        endpoint[3] = if alpha_bits > 0 { bc7_expand(endpoint[3], alpha_bits) } else { 255 };
+    }
+
+    // anchors have their most significant index bit left out, it is always 0
+    let mut indices = [0u32; 16];
+    for (pixel, index) in indices.iter_mut().enumerate() {
+        let (_, anchor) = bc7_subset(mode.subsets, partition, pixel);
+        *index = bits.read(mode.index_bits - anchor as u32);
+    }
+    let mut secondary_indices = [0u32; 16];
+    if mode.secondary_index_bits > 0 {
+        for (pixel, index) in secondary_indices.iter_mut().enumerate() {
+            *index = bits.read(mode.secondary_index_bits - (pixel == 0) as u32);
+        }
+    }
+
+    let weights = |index_bits: u32, index: u32| match index_bits {
+        2 => BC7_WEIGHTS_2[index as usize],
+        3 => BC7_WEIGHTS_3[index as usize],
+        _ => BC7_WEIGHTS_4[index as usize]
+    };
+    let mut pixels = [[0; 4]; 16];
+    for (pixel, rgba) in pixels.iter_mut().enumerate() {
+        let (subset, _) = bc7_subset(mode.subsets, partition, pixel);
+        let [start, end] = endpoints[subset];
+        let primary = weights(mode.index_bits, indices[pixel]);
+        let (color_weight, alpha_weight) = match (mode.secondary_index_bits, selection) {
+            (0, _) => (primary, primary),
+            (bits, 0) => (primary, weights(bits, secondary_indices[pixel])),
+            (bits, _) => (weights(bits, secondary_indices[pixel]), primary)
+        };
+        for channel in 0 .. 4 {
+            let weight = if channel < 3 { color_weight } else { alpha_weight };
+            rgba[channel] = (((64 - weight) * start[channel] + weight * end[channel] + 32) >> 6) as u8;
+        }
+        if rotation > 0 {
+            rgba.swap(rotation as usize - 1, 3);
+        }
+    }
+    pixels
+}
+
+const ETC_MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8], [5, 17], [9, 29], [13, 42], [18, 60], [24, 80], [33, 106], [47, 183]
+];
+
+const ETC_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8]
+];
+
+fn clamp_u8(v: i32) -> u8 {
+    v.clamp(0, 255) as u8
+}
+
+fn extend4(c: u32) -> i32 {
+    (c << 4 | c) as i32
+}
+
+fn extend5(c: u32) -> i32 {
+    (c << 3 | c >> 2) as i32
+}
+
+fn extend6(c: u32) -> i32 {
+    (c << 2 | c >> 4) as i32
+}
+
+fn extend7(c: u32) -> i32 {
+    (c << 1 | c >> 6) as i32
+}
+
+/// ETC1 compatible modes plus the ETC2 T, H and planar modes.
+/// Texels of an ETC block are indexed column by column, the result goes row by row.
+fn decode_etc2_rgb(block: &[u8]) -> [[u8; 4]; 16] {
+    let bits = u64::from_be_bytes(block[.. 8].try_into().unwrap());
+    let field = |high: u32, len: u32| ((bits >> (high + 1 - len)) & ((1 << len) - 1)) as u32;
+    let texel_index = |i: usize| (field(16 + i as u32, 1) << 1 | field(i as u32, 1)) as usize;
+    let mut pixels = [[0; 4]; 16];
+
+    let differential = field(33, 1) == 1;
+    let (r, dr) = (field(63, 5) as i32, ((field(58, 3) as i32) << 29) >> 29);
+    let (g, dg) = (field(55, 5) as i32, ((field(50, 3) as i32) << 29) >> 29);
+    let (b, db) = (field(47, 5) as i32, ((field(42, 3) as i32) << 29) >> 29);
+
+    if differential && !(0 ..= 31).contains(&(r + dr)) {
+        // T mode
+        let c1 = [extend4(field(60, 2) << 2 | field(57, 2)), extend4(field(55, 4)), extend4(field(51, 4))];
+        let c2 = [extend4(field(47, 4)), extend4(field(43, 4)), extend4(field(39, 4))];
+        let d = ETC_DISTANCES[(field(35, 2) << 1 | field(32, 1)) as usize];
+        let paint = [
+            c1,
+            [c2[0] + d, c2[1] + d, c2[2] + d],
+            c2,
+            [c2[0] - d, c2[1] - d, c2[2] - d]
+        ];
+        for i in 0 .. 16 {
+            let c = paint[texel_index(i)];
+            pixels[(i % 4) * 4 + i / 4] = [clamp_u8(c[0]), clamp_u8(c[1]), clamp_u8(c[2]), 255];
+        }
+    } else if differential && !(0 ..= 31).contains(&(g + dg)) {
+        // H mode
+        let c1 = [field(62, 4), field(58, 3) << 1 | field(52, 1), field(51, 1) << 3 | field(49, 3)];
+        let c2 = [field(46, 4), field(42, 4), field(38, 4)];
+        let value = |c: [u32; 3]| c[0] << 8 | c[1] << 4 | c[2];
+        let order = if value(c1) >= value(c2) { 1 } else { 0 };
+        let d = ETC_DISTANCES[(field(34, 1) << 2 | field(32, 1) << 1 | order) as usize];
+        let (c1, c2) = (c1.map(extend4), c2.map(extend4));
+        let paint = [
+            [c1[0] + d, c1[1] + d, c1[2] + d],
+            [c1[0] - d, c1[1] - d, c1[2] - d],
+            [c2[0] + d, c2[1] + d, c2[2] + d],
+            [c2[0] - d, c2[1] - d, c2[2] - d]
+        ];
+        for i in 0 .. 16 {
+            let c = paint[texel_index(i)];
+            pixels[(i % 4) * 4 + i / 4] = [clamp_u8(c[0]), clamp_u8(c[1]), clamp_u8(c[2]), 255];
+        }
+    } else if differential && !(0 ..= 31).contains(&(b + db)) {
+        // planar mode
+        let o = [extend6(field(62, 6)), extend7(field(56, 1) << 6 | field(54, 6)),
+                 extend6(field(48, 1) << 5 | field(44, 2) << 3 | field(41, 3))];
+        let h = [extend6(field(38, 5) << 1 | field(32, 1)), extend7(field(31, 7)), extend6(field(24, 6))];
+        let v = [extend6(field(18, 6)), extend7(field(12, 7)), extend6(field(5, 6))];
+        for y in 0 .. 4 {
+            for x in 0 .. 4 {
+                let c = |i: usize| clamp_u8((x * (h[i] - o[i]) + y * (v[i] - o[i]) + 4 * o[i] + 2) >> 2);
+                pixels[(y * 4 + x) as usize] = [c(0), c(1), c(2), 255];
+            }
+        }
+    } else {
+        let (base1, base2) = if differential {
+            ([extend5(r as u32), extend5(g as u32), extend5(b as u32)],
+             [extend5((r + dr) as u32), extend5((g + dg) as u32), extend5((b + db) as u32)])
+        } else {
+            ([extend4(field(63, 4)), extend4(field(55, 4)), extend4(field(47, 4))],
+             [extend4(field(59, 4)), extend4(field(51, 4)), extend4(field(43, 4))])
+        };
+        let tables = [field(39, 3) as usize, field(36, 3) as usize];
+        let flip = field(32, 1) == 1;
+
+        for i in 0 .. 16 {
+            let (x, y) = (i / 4, i % 4);
+            let subblock = if flip { y / 2 } else { x / 2 };
+            let base = if subblock == 0 { base1 } else { base2 };
+            let [small, large] = ETC_MODIFIERS[tables[subblock]];
+            let modifier = [small, large, -small, -large][texel_index(i)];
+            pixels[y * 4 + x] = [
+                clamp_u8(base[0] + modifier), clamp_u8(base[1] + modifier), clamp_u8(base[2] + modifier), 255
+            ];
+        }
+    }
+    pixels
+}
+
+/// EAC alpha block followed by an ETC2 color block.
+fn decode_etc2_rgba(block: &[u8]) -> [[u8; 4]; 16] {
+    let mut pixels = decode_etc2_rgb(&block[8 ..]);
+    let bits = u64::from_be_bytes(block[.. 8].try_into().unwrap());
+    let base = (bits >> 56) as i32;
+    let multiplier = (bits >> 52 & 0xF) as i32;
+    let table = EAC_MODIFIERS[(bits >> 48 & 0xF) as usize];
+
+    for i in 0 .. 16 {
+        let index = (bits >> (45 - i * 3) & 7) as usize;
+        let (x, y) = (i / 4, i % 4);
+        pixels[y * 4 + x][3] = clamp_u8(base + table[index] * multiplier);
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes fields of a BC7 block from the least significant bit up.
+    struct BitWriter {
+        bits: u128,
+        position: u32
+    }
+
+    impl BitWriter {
+        fn new() -> BitWriter {
+            BitWriter { bits: 0, position: 0 }
+        }
+
+        fn write(&mut self, value: u32, count: u32) {
+            self.bits |= (value as u128) << self.position;
+            self.position += count;
+        }
+
+        fn finish(self) -> [u8; 16] {
+            assert_eq!(self.position, 128);
+            self.bits.to_le_bytes()
+        }
+    }
+
+    fn header(len: usize, magic: &[u8], fields: &[(usize, u32)]) -> Vec<u8> {
+        let mut bytes = vec![0; len];
+        bytes[.. magic.len()].copy_from_slice(magic);
+        for &(offset, value) in fields {
+            bytes[offset .. offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn bc1_palette() {
+        let mut block = [0u8; 8];
+        block[.. 2].copy_from_slice(&0xF800u16.to_le_bytes());
+        block[2 .. 4].copy_from_slice(&0x001Fu16.to_le_bytes());
+        // pixels 0 to 3 use every entry of the palette
+        block[4] = 0b11_10_01_00;
+        let pixels = decode_bc1(&block, true);
+        assert_eq!(pixels[0], [255, 0, 0, 255]);
+        assert_eq!(pixels[1], [0, 0, 255, 255]);
+        assert_eq!(pixels[2], [170, 0, 85, 255]);
+        assert_eq!(pixels[3], [85, 0, 170, 255]);
+        assert_eq!(pixels[4], [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn bc1_punch_through() {
+        let mut block = [0u8; 8];
+        block[2 .. 4].copy_from_slice(&0xFFFFu16.to_le_bytes());
+        block[4] = 0b11_10_01;
+        let pixels = decode_bc1(&block, true);
+        assert_eq!(pixels[0], [255, 255, 255, 255]);
+        assert_eq!(pixels[1], [127, 127, 127, 255]);
+        assert_eq!(pixels[2], [0, 0, 0, 0]);
+        assert_eq!(pixels[3], [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn bc4_channel_palette() {
+        // indices 0, 1 and 2 for the first three pixels
+        let block = [255, 0, 0b10_001_000, 0, 0, 0, 0, 0];
+        let values = decode_bc4_channel(&block);
+        assert_eq!(&values[.. 4], &[255, 0, 218, 255]);
+    }
+
+    #[test]
+    fn bc7_reserved_mode() {
+        assert_eq!(decode_bc7(&[0; 16]), [[0; 4]; 16]);
+    }
+
+    #[test]
+    fn bc7_mode6() {
+        let mut bits = BitWriter::new();
+        bits.write(1 << 6, 7);
+        for _ in 0 .. 4 {
+            bits.write(0, 7);
+            bits.write(127, 7);
+        }
+        bits.write(0, 1);
+        bits.write(1, 1);
+        bits.write(0, 3);
+        bits.write(15, 4);
+        bits.write(8, 4);
+        bits.write(0, 4 * 13);
+        let pixels = decode_bc7(&bits.finish());
+        assert_eq!(pixels[0], [0, 0, 0, 0]);
+        assert_eq!(pixels[1], [255, 255, 255, 255]);
+        assert_eq!(pixels[2], [135, 135, 135, 135]);
+    }
+
+    #[test]
+    fn bc7_mode1_partition() {
+        let mut bits = BitWriter::new();
+        bits.write(0b10, 2);
+        bits.write(0, 6);
+        // red in the first subset and blue in the second
+        for channel in [[63, 0], [0, 0], [0, 63]] {
+            for value in channel {
+                bits.write(value, 6);
+                bits.write(value, 6);
+            }
+        }
+        bits.write(0, 2);
+        bits.write(0, 46);
+        let pixels = decode_bc7(&bits.finish());
+        for (i, pixel) in pixels.iter().enumerate() {
+            let expected = if i % 4 < 2 { [253, 0, 0, 255] } else { [0, 0, 253, 255] };
+            assert_eq!(*pixel, expected);
+        }
+    }
+
+    #[test]
+    fn bc7_anchors_in_their_subsets() {
+        for partition in 0 .. 64 {
+            assert_eq!(bc7_subset(2, partition, 0), (0, true));
+            assert_eq!(bc7_subset(2, partition, BC7_ANCHORS_2[partition]), (1, true));
+            assert_eq!(bc7_subset(3, partition, 0), (0, true));
+            assert_eq!(bc7_subset(3, partition, BC7_ANCHORS_3[0][partition]), (1, true));
+            assert_eq!(bc7_subset(3, partition, BC7_ANCHORS_3[1][partition]), (2, true));
+        }
+    }
+
+    #[test]
+    fn dds_mipmap_count() {
+        let mut bytes = header(128, DDS_MAGIC, &[(8, 0x20000), (12, 4), (16, 4), (28, 3)]);
+        bytes[84 .. 88].copy_from_slice(b"DXT1");
+        bytes.extend_from_slice(&[0; 24]);
+        let image = CompressedImage::parse(&bytes).unwrap();
+        assert_eq!(image.levels.len(), 3);
+        assert_eq!(image.level_dimensions(2), (1, 1));
+
+        bytes[28 .. 32].copy_from_slice(&4u32.to_le_bytes());
+        assert!(matches!(CompressedImage::parse(&bytes), Err(TextureError::Format(_))));
+        bytes[28 .. 32].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(CompressedImage::parse(&bytes), Err(TextureError::Format(_))));
+    }
+
+    #[test]
+    fn ktx1_image_size() {
+        let fields = [(12, 0x04030201), (28, 0x83F0), (36, 4), (40, 4), (52, 1), (56, 1), (64, 8)];
+        let mut bytes = header(68, KTX1_MAGIC, &fields);
+        bytes.extend_from_slice(&[0; 8]);
+        let image = CompressedImage::parse(&bytes).unwrap();
+        assert_eq!(image.levels, vec![vec![0; 8]]);
+
+        bytes[64 .. 68].copy_from_slice(&16u32.to_le_bytes());
+        assert!(matches!(CompressedImage::parse(&bytes), Err(TextureError::Format(_))));
+    }
+}
@@ -0,0 +1,162 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use glium::texture::SrgbTexture2d;
+use image::RgbaImage;
+use super::TextureError;
+
+/// Texture requested through `TextureManager::load_async`, it resolves once the image
+/// is decoded on a worker thread and uploaded on the main thread.
+#[derive(Clone)]
+pub struct TextureHandle {
+    name: String,
+    state: Rc<RefCell<HandleState>>
+}
+
+enum HandleState {
+    Loading,
+    Ready(Rc<Box<SrgbTexture2d>>),
+    Failed
+}
+
+impl TextureHandle {
+    pub(super) fn loading(name: String) -> TextureHandle {
+        TextureHandle {
+            name,
+            state: Rc::new(RefCell::new(HandleState::Loading))
+        }
+    }
+
+    pub(super) fn ready(name: String, texture: Rc<Box<SrgbTexture2d>>) -> TextureHandle {
+        let handle = Self::loading(name);
+        handle.resolve(Some(texture));
+        handle
+    }
+
+    pub(super) fn resolve(&self, texture: Option<Rc<Box<SrgbTexture2d>>>) {
+        *self.state.borrow_mut() = match texture {
+            Some(texture) => HandleState::Ready(texture),
+            None => HandleState::Failed
+        };
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_ready(&self) -> bool {
+        matches!(*self.state.borrow(), HandleState::Ready(_))
+    }
+
+    pub fn is_failed(&self) -> bool {
+        matches!(*self.state.borrow(), HandleState::Failed)
+    }
+
+    /// The texture, once it has been uploaded.
+    pub fn get(&self) -> Option<Rc<Box<SrgbTexture2d>>> {
+        match &*self.state.borrow() {
+            HandleState::Ready(texture) => Some(texture.clone()),
+            _ => None
+        }
+    }
+}
+
+struct Job {
+    name: String,
+    path: PathBuf
+}
+
+pub(super) struct Decoded {
+    pub name: String,
+    pub path: PathBuf,
+    pub result: Result<RgbaImage, TextureError>
+}
+
+/// Pool of worker threads decoding images; GL objects can only be created on the main
+/// thread, so the decoded images are collected by `TextureManager::update`.
+pub(super) struct AsyncLoader {
+    jobs: Option<Sender<Job>>,
+    results_sender: Sender<Decoded>,
+    results: Receiver<Decoded>,
+    pub pending: HashMap<String, TextureHandle>,
+    requested: usize,
+    finished: usize
+}
+
+impl AsyncLoader {
+    pub fn new() -> AsyncLoader {
+        let (results_sender, results) = mpsc::channel();
+        AsyncLoader {
+            jobs: None,
+            results_sender, results,
+            pending: HashMap::new(),
+            requested: 0,
+            finished: 0
+        }
+    }
+
+    pub fn request(&mut self, name: String, path: PathBuf) -> TextureHandle {
+        if let Some(handle) = self.pending.get(&name) {
+            return handle.clone();
+        }
+
+        // a new batch begins once the previous one is complete
+        if self.requested == self.finished {
+            self.requested = 0;
+            self.finished = 0;
+        }
+        self.requested += 1;
+
+        let results = &self.results_sender;
+        let jobs = self.jobs.get_or_insert_with(|| spawn_workers(results.clone()));
+        jobs.send(Job { name: name.clone(), path }).expect("Texture loader threads are gone");
+
+        let handle = TextureHandle::loading(name.clone());
+        self.pending.insert(name, handle.clone());
+        handle
+    }
+
+    pub fn try_receive(&mut self) -> Option<Decoded> {
+        let decoded = self.results.try_recv().ok()?;
+        self.finished += 1;
+        Some(decoded)
+    }
+
+    /// Fraction of the current batch of requests that is done, `1.0` when idle.
+    pub fn progress(&self) -> f32 {
+        if self.requested == 0 {
+            1.0
+        } else {
+            self.finished as f32 / self.requested as f32
+        }
+    }
+}
+
+fn spawn_workers(results: Sender<Decoded>) -> Sender<Job> {
+    let (sender, jobs) = mpsc::channel::<Job>();
+    let jobs = Arc::new(Mutex::new(jobs));
+    let count = thread::available_parallelism().map(|n| n.get()).unwrap_or(2);
+
+    for i in 0 .. count {
+        let jobs = jobs.clone();
+        let results = results.clone();
+        thread::Builder::new()
+            .name(format!("texture-loader-{}", i))
+            .spawn(move || loop {
+                let job = match jobs.lock().expect("Texture loader queue is poisoned").recv() {
+                    Ok(job) => job,
+                    Err(_) => break
+                };
+                let result = super::decode_file(&job.path);
+                if results.send(Decoded { name: job.name, path: job.path, result }).is_err() {
+                    break;
+                }
+            })
+            .expect("Texture loader thread creation failed");
+    }
+    sender
+}
@@ -1,36 +1,142 @@
-use std::collections::HashMap;
-use std::path::Path;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::Arc;
-use glium::{Display, Texture2d};
-use glium::texture::{RawImage2d, SrgbTexture2d};
-use image::{ColorType, GenericImageView};
+use glium::{Display, Rect, Surface};
+use glium::backend::Facade;
+use glium::framebuffer::{SimpleFrameBuffer, ValidationError};
+use glium::texture::{CompressedMipmapsOption, CompressedSrgbTexture2d, CubeLayer, MipmapsOption, RawImage2d,
+                     SrgbCubemap, SrgbTexture2d, SrgbTexture2dArray, TextureCreationError};
+use glium::uniforms::{AsUniformValue, MagnifySamplerFilter, MinifySamplerFilter, SamplerBehavior, UniformValue};
+use image::{GenericImageView, RgbaImage};
+
+mod atlas;
+mod compressed;
+mod loader;
+
+pub use atlas::{AtlasBuilder, RectPacker, TextureAtlas, TextureRegion};
+pub use compressed::{BlockFormat, CompressedImage};
+pub use loader::TextureHandle;
 
 pub fn load<N>(display: &Display, name: N) -> Arc<SrgbTexture2d> where N: AsRef<Path> {
-    let image = image::open(name).expect("unable to open image");
+    try_load(display, name).expect("unable to load texture")
+}
+
+pub fn try_load<N>(display: &Display, name: N) -> Result<Arc<SrgbTexture2d>, TextureError> where N: AsRef<Path> {
+    let image = image::open(name)?;
     let size = image.dimensions();
     let raw = RawImage2d::from_raw_rgba_reversed(&image.into_rgba8(), size);
-    let texture = SrgbTexture2d::new(display, raw).expect("failed to allocate texture");
-    Arc::new(texture)
+    let texture = SrgbTexture2d::with_mipmaps(display, raw, MipmapsOption::AutoGeneratedMipmaps)?;
+    Ok(Arc::new(texture))
+}
+
+/// Magenta and black checkerboard shown in place of textures that failed to load.
+fn create_placeholder(display: &Display) -> Result<SrgbTexture2d, TextureError> {
+    const SIZE: u32 = 16;
+    const CELL: u32 = 8;
+    let image = image::RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+        if (x / CELL + y / CELL) % 2 == 0 {
+            image::Rgba([255, 0, 255, 255])
+        } else {
+            image::Rgba([0, 0, 0, 255])
+        }
+    });
+    let image = RawImage2d::from_raw_rgba(image.into_raw(), (SIZE, SIZE));
+    Ok(SrgbTexture2d::with_mipmaps(display, image, MipmapsOption::AutoGeneratedMipmaps)?)
 }
 
 pub struct TextureManager {
     pub display: Display,
-    pub textures: HashMap<String, Rc<Box<SrgbTexture2d>>>
+    pub textures: HashMap<String, Rc<Box<SrgbTexture2d>>>,
+    /// Block compressed textures kept compressed on the GPU, see `load_compressed`.
+    pub compressed: HashMap<String, Rc<Box<CompressedSrgbTexture2d>>>,
+    /// Layered textures, see `load_array`.
+    pub arrays: HashMap<String, Rc<Box<SrgbTexture2dArray>>>,
+    pub cubemaps: HashMap<String, Rc<Box<SrgbCubemap>>>,
+    pub atlases: HashMap<String, TextureAtlas>,
+    pub regions: HashMap<String, TextureRegion>,
+    pub samplers: HashMap<String, SamplerBehavior>,
+    /// Sampling used for textures without their own entry in `samplers`.
+    pub default_sampler: SamplerBehavior,
+    /// Whether `get` and `get_or_load` return `placeholder` instead of panicking
+    /// on a missing or broken texture. Enabled by default in release builds.
+    pub use_placeholder: bool,
+    pub placeholder: Rc<Box<SrgbTexture2d>>,
+    /// Estimated GPU memory, in bytes, above which the least recently used textures
+    /// loaded from files are evicted. They are loaded again in the background when fetched,
+    /// `placeholder` stands in for them meanwhile.
+    pub memory_budget: Option<usize>,
+    groups: HashMap<String, HashSet<String>>,
+    sources: HashMap<String, PathBuf>,
+    // dimensions of the textures evicted by `enforce_budget`
+    evicted: HashMap<String, (u32, u32)>,
+    // evicted textures fetched since the last `update`, which requests them from `loader`
+    reload_requests: RefCell<HashSet<String>>,
+    // block format of every texture in `compressed`, for the memory estimate
+    formats: HashMap<String, BlockFormat>,
+    last_used: RefCell<HashMap<String, u64>>,
+    // textures whose loading error was printed, see `report`
+    reported: RefCell<HashSet<String>>,
+    frame: u64,
+    loader: loader::AsyncLoader
+}
+
+/// Texture of either kind bound with its sampler settings, see `TextureManager::uniform`.
+pub enum TextureUniform {
+    Srgb(Rc<Box<SrgbTexture2d>>, SamplerBehavior),
+    Compressed(Rc<Box<CompressedSrgbTexture2d>>, SamplerBehavior),
+    Array(Rc<Box<SrgbTexture2dArray>>, SamplerBehavior),
+    Cubemap(Rc<Box<SrgbCubemap>>, SamplerBehavior)
+}
+
+impl AsUniformValue for TextureUniform {
+    fn as_uniform_value(&self) -> UniformValue<'_> {
+        match self {
+            TextureUniform::Srgb(texture, sampler) => UniformValue::SrgbTexture2d(texture, Some(*sampler)),
+            TextureUniform::Compressed(texture, sampler) => UniformValue::CompressedSrgbTexture2d(texture, Some(*sampler)),
+            TextureUniform::Array(texture, sampler) => UniformValue::SrgbTexture2dArray(texture, Some(*sampler)),
+            TextureUniform::Cubemap(texture, sampler) => UniformValue::SrgbCubemap(texture, Some(*sampler))
+        }
+    }
 }
 
+/// Dimensions and estimated memory of a texture, as reported by `TextureManager::info`.
+#[derive(Clone, Debug)]
+pub struct TextureInfo {
+    pub name: String,
+    pub dimensions: (u32, u32),
+    /// 1 for plain textures, the number of layers of arrays and 6 for cubemaps.
+    pub layers: u32,
+    pub mipmap_levels: u32,
+    pub bytes: usize,
+    /// Frame number when the texture was last fetched.
+    pub last_used: u64
+}
+
+/// Embeds `resources/textures/{name}.png` into the binary and registers it in a `TextureManager`
+/// under `name`, evaluates to the result of `TextureManager::load_from_memory`.
 #[macro_export]
 macro_rules! texture {
     ($manager:expr, $name:literal) => {{
-        use crate::image::{self, GenericImageView};
-        static IMAGE_BUF: &'static [u8] = include_bytes!(concat!("resources/", $name, ".png"));
-        let manager = $manager;
-        let image = image::load_from_memory_with_format(&IMAGE_BUF, image::ImageFormat::Png)
-            .expect("Image loading failed");
-        let size = image.dimensions();
-        let image = glium::texture::RawImage2d::from_raw_rgba(image.raw_pixels(), size);
-        let texture = glium::texture::SrgbTexture2d::new(manager.display, image).expect("Texture allocation failed");
-        manager.textures.insert($name.into(), std::rc::Rc::new(Box::new(texture)));
+        static IMAGE_BUF: &'static [u8] = include_bytes!(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/resources/textures/", $name, ".png")
+        );
+        $manager.load_from_memory($name.into(), IMAGE_BUF)
+    }};
+}
+
+/// Embeds a whole folder of images into the binary and registers them in a `TextureManager`,
+/// see `TextureManager::load_bundle`. The path is absolute, `$CARGO_MANIFEST_DIR` is expanded.
+///
+/// ```ignore
+/// texture_bundle!(textures, "$CARGO_MANIFEST_DIR/resources/textures");
+/// ```
+#[macro_export]
+macro_rules! texture_bundle {
+    ($manager:expr, $path:tt) => {{
+        static BUNDLE: include_dir::Dir<'static> = include_dir::include_dir!($path);
+        $manager.load_bundle(&BUNDLE)
     }};
 }
 
@@ -38,29 +144,787 @@ impl TextureManager {
     pub fn new(display: &Display) -> TextureManager {
         TextureManager {
             display: display.clone(),
-            textures: HashMap::new()
+            textures: HashMap::new(),
+            compressed: HashMap::new(),
+            arrays: HashMap::new(),
+            cubemaps: HashMap::new(),
+            atlases: HashMap::new(),
+            regions: HashMap::new(),
+            samplers: HashMap::new(),
+            default_sampler: SamplerBehavior {
+                magnify_filter: MagnifySamplerFilter::Nearest,
+                minify_filter: MinifySamplerFilter::NearestMipmapNearest,
+                .. Default::default()
+            },
+            use_placeholder: !cfg!(debug_assertions),
+            placeholder: Rc::new(Box::new(
+                create_placeholder(display).expect("Placeholder texture allocation failed")
+            )),
+            memory_budget: None,
+            groups: HashMap::new(),
+            sources: HashMap::new(),
+            evicted: HashMap::new(),
+            reload_requests: RefCell::new(HashSet::new()),
+            formats: HashMap::new(),
+            last_used: RefCell::new(HashMap::new()),
+            reported: RefCell::new(HashSet::new()),
+            frame: 0,
+            loader: loader::AsyncLoader::new()
+        }
+    }
+
+    fn insert(&mut self, name: String, texture: SrgbTexture2d, source: Option<PathBuf>) -> Rc<Box<SrgbTexture2d>> {
+        let texture = Rc::new(Box::new(texture));
+        self.textures.insert(name.clone(), texture.clone());
+        self.evicted.remove(&name);
+        self.reported.get_mut().remove(&name);
+        if let Some(source) = source {
+            self.sources.insert(name.clone(), source);
+        }
+        self.last_used.borrow_mut().insert(name, self.frame);
+        texture
+    }
+
+    /// Prints the error of a texture the first time it fails, not on every draw.
+    fn report(&self, name: &str, error: &TextureError) {
+        if self.reported.borrow_mut().insert(name.into()) {
+            eprintln!("Image loading failed: {}: {:?}", name, error);
+        }
+    }
+
+    fn touch(&self, name: &str) {
+        let mut last_used = self.last_used.borrow_mut();
+        match last_used.get_mut(name) {
+            Some(frame) => *frame = self.frame,
+            None => {
+                last_used.insert(name.into(), self.frame);
+            }
         }
     }
 
+    /// Sampler settings (filtering, wrap mode, anisotropy) to draw the texture with.
+    pub fn sampler<T>(&self, name: T) -> SamplerBehavior where T: AsRef<str> {
+        self.samplers.get(name.as_ref()).cloned().unwrap_or(self.default_sampler)
+    }
+
+    pub fn set_sampler<T>(&mut self, name: T, sampler: SamplerBehavior) where T: Into<String> {
+        self.samplers.insert(name.into(), sampler);
+    }
+
+    /// Loads a texture like `get_or_load` and assigns it sampler settings.
+    pub fn get_or_load_with_sampler<P>(&mut self, name: String, path: P, sampler: SamplerBehavior)
+        -> Option<Rc<Box<SrgbTexture2d>>> where P: AsRef<Path> {
+
+        self.samplers.insert(name.clone(), sampler);
+        self.get_or_load(name, path)
+    }
+
     pub fn get<T>(&self, name: T) -> Rc<Box<SrgbTexture2d>> where T: AsRef<str> {
-        self.textures.get(name.as_ref()).cloned().expect(&format!("Missing texture: {}", name.as_ref()))
+        match self.try_get(name.as_ref()) {
+            Ok(texture) => texture,
+            Err(TextureError::Reloading(_)) => self.placeholder.clone(),
+            Err(e) if self.use_placeholder => {
+                self.report(name.as_ref(), &e);
+                self.placeholder.clone()
+            }
+            Err(e) => panic!("{:?}", e)
+        }
     }
 
+    /// Texture with this name. A texture evicted by `enforce_budget` is requested from the
+    /// worker threads again and is `TextureError::Reloading` until `update` uploads it.
+    pub fn try_get<T>(&self, name: T) -> Result<Rc<Box<SrgbTexture2d>>, TextureError> where T: AsRef<str> {
+        let name = name.as_ref();
+        let texture = match self.textures.get(name) {
+            Some(texture) => texture.clone(),
+            None if self.evicted.contains_key(name) => {
+                self.reload_requests.borrow_mut().insert(name.into());
+                return Err(TextureError::Reloading(name.into()));
+            }
+            None => return Err(TextureError::Missing(name.into()))
+        };
+        self.touch(name);
+        Ok(texture)
+    }
+
+    /// Texture of any kind with this name ready to be passed as a sampler uniform.
+    /// Missing textures are handled like in `get`.
+    pub fn uniform<T>(&self, name: T) -> TextureUniform where T: AsRef<str> {
+        let name = name.as_ref();
+        let sampler = self.sampler(name);
+        if let Some(texture) = self.compressed.get(name) {
+            self.touch(name);
+            TextureUniform::Compressed(texture.clone(), sampler)
+        } else if let Some(array) = self.arrays.get(name) {
+            self.touch(name);
+            TextureUniform::Array(array.clone(), sampler)
+        } else if let Some(cubemap) = self.cubemaps.get(name) {
+            self.touch(name);
+            TextureUniform::Cubemap(cubemap.clone(), sampler)
+        } else {
+            TextureUniform::Srgb(self.get(name), sampler)
+        }
+    }
+
+    /// Loads a texture unless it's already present. A texture that can't be loaded is
+    /// replaced with `placeholder` when `use_placeholder` is set, otherwise `None` is returned.
     pub fn get_or_load<P>(&mut self, name: String, path: P) -> Option<Rc<Box<SrgbTexture2d>>> where P: AsRef<Path> {
+        match self.try_get_or_load(name.clone(), path) {
+            Ok(texture) => Some(texture),
+            Err(e) => {
+                self.report(&name, &e);
+                if self.use_placeholder {
+                    Some(self.placeholder.clone())
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    pub fn try_get_or_load<P>(&mut self, name: String, path: P) -> Result<Rc<Box<SrgbTexture2d>>, TextureError>
+        where P: AsRef<Path> {
+
         if !self.textures.contains_key(&name) {
-            let image = image::open(path.as_ref())
-                .expect(&format!("Image loading failed: {}", name));
-
-            let size = image.dimensions();
-            let has_alpha = image.color().has_alpha();
-            let image: RawImage2d<u8> = if has_alpha {
-                RawImage2d::from_raw_rgba(image.into_rgba8().into_raw(), size)
-            } else {
-                RawImage2d::from_raw_rgb(image.into_rgb8().into_raw(), size)
+            let image = image::open(path.as_ref())?;
+            let texture = self.upload(image)?;
+            return Ok(self.insert(name, texture, Some(path.as_ref().to_path_buf())));
+        }
+        self.try_get(&name)
+    }
+
+    /// Registers an image file embedded in the binary, the format is guessed from the contents.
+    /// Errors are handled like in `get_or_load`.
+    pub fn load_from_memory(&mut self, name: String, bytes: &[u8]) -> Option<Rc<Box<SrgbTexture2d>>> {
+        match self.try_load_from_memory(name.clone(), bytes) {
+            Ok(texture) => Some(texture),
+            Err(e) => {
+                self.report(&name, &e);
+                if self.use_placeholder {
+                    Some(self.placeholder.clone())
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    pub fn try_load_from_memory(&mut self, name: String, bytes: &[u8]) -> Result<Rc<Box<SrgbTexture2d>>, TextureError> {
+        let image = image::load_from_memory(bytes)?;
+        let texture = self.upload(image)?;
+        Ok(self.insert(name, texture, None))
+    }
+
+    /// Registers every image of an embedded folder (recursively). Textures are named after
+    /// their path inside the folder without the extension, e.g. `"ui/button"`.
+    /// Returns the number of registered textures.
+    pub fn load_bundle(&mut self, bundle: &include_dir::Dir) -> Result<usize, TextureError> {
+        let mut count = 0;
+        for file in bundle.files() {
+            if image::ImageFormat::from_path(file.path()).is_err() {
+                continue;
+            }
+            let name = file.path().with_extension("").to_string_lossy().replace('\\', "/");
+            self.try_load_from_memory(name, file.contents())?;
+            count += 1;
+        }
+        for dir in bundle.dirs() {
+            count += self.load_bundle(dir)?;
+        }
+        Ok(count)
+    }
+
+    /// Loads a DDS, KTX or KTX2 file with its mipmaps. BC1, BC2, BC3 and BC7 data stays
+    /// compressed on the GPU when the driver supports the format; otherwise, and for BC4,
+    /// BC5 and ETC2, the first level is decompressed on the CPU and uploaded as a regular
+    /// texture. BC6H can't be decompressed.
+    ///
+    /// Either way the texture is drawn by name, see `uniform`.
+    pub fn load_compressed<P>(&mut self, name: String, path: P) -> Result<(), TextureError> where P: AsRef<Path> {
+        if self.compressed.contains_key(&name) || self.textures.contains_key(&name) {
+            return Ok(());
+        }
+        let bytes = std::fs::read(path.as_ref())?;
+        self.load_compressed_from_memory(name.clone(), &bytes)?;
+        if self.textures.contains_key(&name) {
+            self.sources.insert(name, path.as_ref().to_path_buf());
+        }
+        Ok(())
+    }
+
+    pub fn load_compressed_from_memory(&mut self, name: String, bytes: &[u8]) -> Result<(), TextureError> {
+        let image = CompressedImage::parse(bytes)?;
+
+        let gpu_format = image.format.gpu_format()
+            .filter(|format| format.is_supported(&**self.display.get_context()));
+        if let Some(format) = gpu_format {
+            let mipmaps = match image.levels.len() {
+                1 => CompressedMipmapsOption::NoMipmap,
+                count => CompressedMipmapsOption::EmptyMipmapsMax(count as u32 - 1)
             };
-            let texture = SrgbTexture2d::new(&self.display, image).expect("Texture allocation failed");
-            self.textures.insert(name.clone(), Rc::new(Box::new(texture)));
+            let texture = CompressedSrgbTexture2d::with_compressed_data(
+                &self.display, &image.levels[0], image.width, image.height, format, mipmaps
+            )?;
+            for (level, data) in image.levels.iter().enumerate().skip(1) {
+                let (width, height) = image.level_dimensions(level);
+                let rect = Rect { left: 0, bottom: 0, width, height };
+                texture.mipmap(level as u32)
+                    .and_then(|mipmap| mipmap.write_compressed_data(rect, data, width, height, format).ok())
+                    .ok_or_else(|| TextureError::Format(format!("Mipmap level {} upload failed", level)))?;
+            }
+            self.compressed.insert(name.clone(), Rc::new(Box::new(texture)));
+            self.formats.insert(name.clone(), image.format);
+            self.last_used.borrow_mut().insert(name, self.frame);
+            return Ok(());
+        }
+
+        let decoded = image.decompress(0)?;
+        let size = decoded.dimensions();
+        let raw = RawImage2d::from_raw_rgba(decoded.into_raw(), size);
+        let texture = SrgbTexture2d::with_mipmaps(&self.display, raw, MipmapsOption::AutoGeneratedMipmaps)?;
+        self.insert(name, texture, None);
+        Ok(())
+    }
+
+    fn upload(&self, image: image::DynamicImage) -> Result<SrgbTexture2d, TextureError> {
+        let size = image.dimensions();
+        let has_alpha = image.color().has_alpha();
+        let image: RawImage2d<u8> = if has_alpha {
+            RawImage2d::from_raw_rgba(image.into_rgba8().into_raw(), size)
+        } else {
+            RawImage2d::from_raw_rgb(image.into_rgb8().into_raw(), size)
+        };
+        Ok(SrgbTexture2d::with_mipmaps(&self.display, image, MipmapsOption::AutoGeneratedMipmaps)?)
+    }
+
+    /// Registers a texture created from RGBA8 pixels, e.g. a generated noise map. The texture
+    /// has no mipmaps so that it can be updated cheaply with `write_pixels`, its sampler is
+    /// set to non-mipmapped filtering unless one is already assigned.
+    pub fn create_from_pixels(&mut self, name: String, size: (u32, u32), pixels: Vec<u8>)
+        -> Result<Rc<Box<SrgbTexture2d>>, TextureError> {
+
+        let expected = (size.0 as usize).checked_mul(size.1 as usize).and_then(|count| count.checked_mul(4));
+        if expected != Some(pixels.len()) {
+            return Err(TextureError::Size(format!("{}: {} bytes for {}x{} pixels", name, pixels.len(), size.0, size.1)));
+        }
+        let image = RawImage2d::from_raw_rgba(pixels, size);
+        let texture = SrgbTexture2d::with_mipmaps(&self.display, image, MipmapsOption::NoMipmap)?;
+
+        if !self.samplers.contains_key(&name) {
+            let minify_filter = match self.default_sampler.magnify_filter {
+                MagnifySamplerFilter::Nearest => MinifySamplerFilter::Nearest,
+                MagnifySamplerFilter::Linear => MinifySamplerFilter::Linear
+            };
+            self.samplers.insert(name.clone(), SamplerBehavior { minify_filter, .. self.default_sampler });
+        }
+        Ok(self.insert(name, texture, None))
+    }
+
+    pub fn create_from_image(&mut self, name: String, image: image::RgbaImage)
+        -> Result<Rc<Box<SrgbTexture2d>>, TextureError> {
+
+        let size = image.dimensions();
+        self.create_from_pixels(name, size, image.into_raw())
+    }
+
+    /// Overwrites a sub-rectangle of a texture, `(x, y)` being the top left corner in pixels.
+    /// Only the base level is written, mipmaps of textures loaded from files get stale.
+    pub fn write_pixels<T>(&self, name: T, x: u32, y: u32, image: &image::RgbaImage) -> Result<(), TextureError>
+        where T: AsRef<str> {
+
+        let texture = self.try_get(name.as_ref())?;
+        let (width, height) = image.dimensions();
+        let (texture_width, texture_height) = texture.dimensions();
+        let fits = |start: u32, len: u32, max: u32| matches!(start.checked_add(len), Some(end) if end <= max);
+        if !fits(x, width, texture_width) || !fits(y, height, texture_height) {
+            return Err(TextureError::OutOfBounds(name.as_ref().into()));
         }
-        self.textures.get(&name).cloned()
+
+        // rows are uploaded top first, like every other texture of the manager
+        let rect = Rect { left: x, bottom: y, width, height };
+        texture.write(rect, RawImage2d::from_raw_rgba(image.as_raw().clone(), (width, height)));
+        Ok(())
+    }
+
+    /// Downloads the base level of a texture. This stalls until the GPU is done with it.
+    pub fn read_pixels<T>(&self, name: T) -> Result<image::RgbaImage, TextureError> where T: AsRef<str> {
+        let texture = self.try_get(name.as_ref())?;
+        let raw: RawImage2d<u8> = texture.read();
+        Ok(image::RgbaImage::from_raw(raw.width, raw.height, raw.data.into_owned())
+            .expect("Texture read back has the wrong size"))
     }
-}
\ No newline at end of file
+
+    /// Loads images of the same size as the layers of a texture array, in order.
+    pub fn load_array<P>(&mut self, name: String, paths: &[P]) -> Result<Rc<Box<SrgbTexture2dArray>>, TextureError>
+        where P: AsRef<Path> {
+
+        if let Some(array) = self.arrays.get(&name) {
+            return Ok(array.clone());
+        }
+        let layers = paths.iter()
+            .map(|path| Ok(image::open(path)?.into_rgba8()))
+            .collect::<Result<Vec<_>, TextureError>>()?;
+        self.create_array(name, layers)
+    }
+
+    pub fn create_array(&mut self, name: String, layers: Vec<RgbaImage>) -> Result<Rc<Box<SrgbTexture2dArray>>, TextureError> {
+        let size = layers.first()
+            .ok_or_else(|| TextureError::Layout(format!("{}: no layers", name)))?
+            .dimensions();
+        if layers.iter().any(|layer| layer.dimensions() != size) {
+            return Err(TextureError::Layout(format!("{}: layers have different sizes", name)));
+        }
+
+        let layers = layers.into_iter()
+            .map(|layer| RawImage2d::from_raw_rgba(layer.into_raw(), size))
+            .collect::<Vec<_>>();
+        let array = SrgbTexture2dArray::with_mipmaps(&self.display, layers, MipmapsOption::AutoGeneratedMipmaps)?;
+        let array = Rc::new(Box::new(array));
+        self.arrays.insert(name, array.clone());
+        Ok(array)
+    }
+
+    pub fn get_array<T>(&self, name: T) -> Option<Rc<Box<SrgbTexture2dArray>>> where T: AsRef<str> {
+        self.arrays.get(name.as_ref()).cloned()
+    }
+
+    /// Loads the six faces of a cubemap in the order +X, -X, +Y, -Y, +Z, -Z.
+    pub fn load_cubemap<P>(&mut self, name: String, faces: &[P; 6]) -> Result<Rc<Box<SrgbCubemap>>, TextureError>
+        where P: AsRef<Path> {
+
+        if let Some(cubemap) = self.cubemaps.get(&name) {
+            return Ok(cubemap.clone());
+        }
+        let faces = faces.iter()
+            .map(|path| Ok(image::open(path)?.into_rgba8()))
+            .collect::<Result<Vec<_>, TextureError>>()?;
+        self.create_cubemap(name, faces)
+    }
+
+    /// Loads a cubemap from a single image with the faces laid out as a horizontal (4x3)
+    /// or vertical (3x4) cross.
+    pub fn load_cubemap_cross<P>(&mut self, name: String, path: P) -> Result<Rc<Box<SrgbCubemap>>, TextureError>
+        where P: AsRef<Path> {
+
+        if let Some(cubemap) = self.cubemaps.get(&name) {
+            return Ok(cubemap.clone());
+        }
+        let image = image::open(path)?.into_rgba8();
+        let faces = split_cross(&image).ok_or_else(|| TextureError::Layout(format!("{}: not a cross layout", name)))?;
+        self.create_cubemap(name, faces)
+    }
+
+    pub fn create_cubemap(&mut self, name: String, faces: Vec<RgbaImage>) -> Result<Rc<Box<SrgbCubemap>>, TextureError> {
+        const LAYERS: [CubeLayer; 6] = [
+            CubeLayer::PositiveX, CubeLayer::NegativeX,
+            CubeLayer::PositiveY, CubeLayer::NegativeY,
+            CubeLayer::PositiveZ, CubeLayer::NegativeZ
+        ];
+
+        let size = faces.first().map(|face| face.width()).unwrap_or(0);
+        if faces.len() != 6 || faces.iter().any(|face| face.dimensions() != (size, size)) {
+            return Err(TextureError::Layout(format!("{}: a cubemap needs 6 square faces of the same size", name)));
+        }
+
+        // cubemap faces can't be written directly, each one is blitted from a temporary texture
+        let cubemap = SrgbCubemap::empty_with_mipmaps(&self.display, MipmapsOption::EmptyMipmaps, size)?;
+        for (layer, face) in LAYERS.iter().zip(faces) {
+            let face = RawImage2d::from_raw_rgba(face.into_raw(), (size, size));
+            let face = SrgbTexture2d::with_mipmaps(&self.display, face, MipmapsOption::NoMipmap)?;
+            let source = SimpleFrameBuffer::new(&self.display, &face)?;
+            let target = SimpleFrameBuffer::new(&self.display, cubemap.main_level().image(*layer))?;
+            source.fill(&target, MagnifySamplerFilter::Nearest);
+        }
+        // the mipmaps were allocated with the texture
+        unsafe { cubemap.generate_mipmaps(); }
+
+        let cubemap = Rc::new(Box::new(cubemap));
+        self.cubemaps.insert(name, cubemap.clone());
+        Ok(cubemap)
+    }
+
+    pub fn get_cubemap<T>(&self, name: T) -> Option<Rc<Box<SrgbCubemap>>> where T: AsRef<str> {
+        self.cubemaps.get(name.as_ref()).cloned()
+    }
+
+    /// Packs the images of `builder` and uploads the pages, which are registered as
+    /// `"{name}#{index}"` textures. Regions become available through `region`.
+    /// Region names are shared by all atlases, nothing is loaded if another atlas already
+    /// defines one of the regions.
+    pub fn load_atlas<N>(&mut self, name: N, builder: AtlasBuilder) -> Result<&TextureAtlas, TextureError>
+        where N: Into<String> {
+
+        let name = name.into();
+        let (pages, regions) = builder.build(&name);
+
+        let duplicate = regions.keys().find_map(|region| {
+            self.atlases.iter()
+                .find(|(atlas, other)| **atlas != name && other.regions.contains_key(region))
+                .map(|(atlas, _)| (region, atlas))
+        });
+        if let Some((region, atlas)) = duplicate {
+            return Err(TextureError::DuplicateRegion(format!("{}: {} is already defined by {}", name, region, atlas)));
+        }
+
+        let textures = pages.into_iter()
+            .map(|page| {
+                let size = page.dimensions();
+                let image = RawImage2d::from_raw_rgba(page.into_raw(), size);
+                SrgbTexture2d::with_mipmaps(&self.display, image, MipmapsOption::AutoGeneratedMipmaps)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut page_names = Vec::with_capacity(textures.len());
+        for (index, texture) in textures.into_iter().enumerate() {
+            let page_name = format!("{}#{}", name, index);
+            self.insert(page_name.clone(), texture, None);
+            page_names.push(page_name);
+        }
+
+        for (region_name, region) in &regions {
+            self.regions.insert(region_name.clone(), region.clone());
+        }
+
+        self.atlases.insert(name.clone(), TextureAtlas {
+            pages: page_names,
+            regions
+        });
+        Ok(self.atlases.get(&name).unwrap())
+    }
+
+    /// Named region of a loaded atlas, or the whole texture if there is a texture with this name.
+    pub fn region<T>(&self, name: T) -> Option<TextureRegion> where T: AsRef<str> {
+        let name = name.as_ref();
+        self.regions.get(name).cloned().or_else(|| {
+            self.try_get(name).ok().map(|texture| TextureRegion::full(name, texture.dimensions()))
+        }).or_else(|| {
+            self.evicted.get(name).map(|size| TextureRegion::full(name, *size))
+        }).or_else(|| {
+            self.compressed.get(name).map(|texture| TextureRegion::full(name, texture.dimensions()))
+        })
+    }
+
+    pub fn try_region<T>(&self, name: T) -> Result<TextureRegion, TextureError> where T: AsRef<str> {
+        self.region(name.as_ref()).ok_or_else(|| TextureError::Missing(name.as_ref().into()))
+    }
+
+    /// Region like `region`, a missing one is handled like a missing texture in `get`:
+    /// the whole `placeholder` is drawn in its place when `use_placeholder` is set.
+    pub fn get_region<T>(&self, name: T) -> TextureRegion where T: AsRef<str> {
+        match self.try_region(name.as_ref()) {
+            Ok(region) => region,
+            // `uniform` reports the missing texture and hands out the placeholder
+            Err(_) if self.use_placeholder => TextureRegion::full(name.as_ref(), self.placeholder.dimensions()),
+            Err(e) => panic!("{:?}", e)
+        }
+    }
+
+    /// Decodes the image on a worker thread, the texture is uploaded by `update`
+    /// on one of the following frames.
+    ///
+    /// ```ignore
+    /// let handle = textures.load_async("background".into(), "resources/textures/background.png");
+    /// // while loading
+    /// canvas.rect([x, y, w * textures.progress(), h], color, &*shader, &uniforms, &params);
+    /// ```
+    pub fn load_async<P>(&mut self, name: String, path: P) -> TextureHandle where P: AsRef<Path> {
+        match self.textures.get(&name) {
+            Some(texture) => TextureHandle::ready(name, texture.clone()),
+            None => self.loader.request(name, path.as_ref().to_path_buf())
+        }
+    }
+
+    /// Uploads the images decoded by the worker threads since the last call and evicts
+    /// textures past `memory_budget`. Called by the window loop before every frame.
+    pub fn update(&mut self) {
+        self.frame += 1;
+        for name in std::mem::take(self.reload_requests.get_mut()) {
+            if let Some(path) = self.sources.get(&name) {
+                self.loader.request(name, path.clone());
+            }
+        }
+        while let Some(decoded) = self.loader.try_receive() {
+            let handle = self.loader.pending.remove(&decoded.name);
+            let result = decoded.result.and_then(|image| {
+                let size = image.dimensions();
+                let image = RawImage2d::from_raw_rgba(image.into_raw(), size);
+                Ok(SrgbTexture2d::with_mipmaps(&self.display, image, MipmapsOption::AutoGeneratedMipmaps)?)
+            });
+
+            let texture = match result {
+                Ok(texture) => Some(self.insert(decoded.name, texture, Some(decoded.path))),
+                Err(e) => {
+                    // a texture that can't be loaded again is missing from now on
+                    self.evicted.remove(&decoded.name);
+                    self.report(&decoded.name, &e);
+                    if self.use_placeholder {
+                        Some(self.placeholder.clone())
+                    } else {
+                        None
+                    }
+                }
+            };
+            if let Some(handle) = handle {
+                handle.resolve(texture);
+            }
+        }
+        self.enforce_budget();
+    }
+
+    /// Progress of the textures requested with `load_async`, from `0.0` to `1.0`.
+    pub fn progress(&self) -> f32 {
+        self.loader.progress()
+    }
+
+    pub fn is_loading(&self) -> bool {
+        !self.loader.pending.is_empty()
+    }
+
+    /// Removes a texture, or all pages and regions of an atlas with this name.
+    pub fn unload<T>(&mut self, name: T) -> bool where T: AsRef<str> {
+        let name = name.as_ref();
+
+        if let Some(atlas) = self.atlases.remove(name) {
+            for page in &atlas.pages {
+                self.unload(page);
+            }
+            for region in atlas.regions.keys() {
+                self.regions.remove(region);
+            }
+            return true;
+        }
+
+        self.samplers.remove(name);
+        self.sources.remove(name);
+        self.formats.remove(name);
+        self.evicted.remove(name);
+        self.reload_requests.get_mut().remove(name);
+        self.reported.get_mut().remove(name);
+        self.last_used.borrow_mut().remove(name);
+        for group in self.groups.values_mut() {
+            group.remove(name);
+        }
+        self.arrays.remove(name).is_some() | self.cubemaps.remove(name).is_some()
+            | self.compressed.remove(name).is_some() | self.textures.remove(name).is_some()
+    }
+
+    /// Adds a texture or an atlas to a group, see `unload_group`.
+    pub fn add_to_group<G, T>(&mut self, group: G, name: T) where G: Into<String>, T: Into<String> {
+        self.groups.entry(group.into()).or_default().insert(name.into());
+    }
+
+    /// Loads a texture like `get_or_load` and adds it to a group.
+    pub fn get_or_load_in_group<G, P>(&mut self, group: G, name: String, path: P) -> Option<Rc<Box<SrgbTexture2d>>>
+        where G: Into<String>, P: AsRef<Path> {
+
+        self.add_to_group(group, name.clone());
+        self.get_or_load(name, path)
+    }
+
+    pub fn group<G>(&self, group: G) -> Option<&HashSet<String>> where G: AsRef<str> {
+        self.groups.get(group.as_ref())
+    }
+
+    /// Drops a group, unloading its textures unless another group still references them.
+    pub fn unload_group<G>(&mut self, group: G) where G: AsRef<str> {
+        let names = match self.groups.remove(group.as_ref()) {
+            Some(names) => names,
+            None => return
+        };
+        for name in names {
+            if !self.groups.values().any(|group| group.contains(&name)) {
+                self.unload(&name);
+            }
+        }
+    }
+
+    pub fn info<T>(&self, name: T) -> Option<TextureInfo> where T: AsRef<str> {
+        let name = name.as_ref();
+        if let Some(texture) = self.compressed.get(name) {
+            return Some(TextureInfo {
+                name: name.into(),
+                dimensions: texture.dimensions(),
+                layers: 1,
+                mipmap_levels: texture.get_mipmap_levels(),
+                bytes: estimate_compressed_bytes(texture, self.formats.get(name).copied()),
+                last_used: self.last_used.borrow().get(name).cloned().unwrap_or(0)
+            });
+        }
+        let (dimensions, layers, mipmap_levels) = if let Some(array) = self.arrays.get(name) {
+            (array.dimensions(), array.array_size(), array.get_mipmap_levels())
+        } else if let Some(cubemap) = self.cubemaps.get(name) {
+            let size = cubemap.dimensions();
+            ((size, size), 6, cubemap.get_mipmap_levels())
+        } else {
+            let texture = self.textures.get(name)?;
+            (texture.dimensions(), 1, texture.get_mipmap_levels())
+        };
+        Some(TextureInfo {
+            name: name.into(),
+            dimensions,
+            layers,
+            mipmap_levels,
+            bytes: estimate_bytes(dimensions, mipmap_levels) * layers as usize,
+            last_used: self.last_used.borrow().get(name).cloned().unwrap_or(0)
+        })
+    }
+
+    /// Information about every loaded texture, the biggest ones first.
+    pub fn stats(&self) -> Vec<TextureInfo> {
+        let mut stats = self.textures.keys().chain(self.compressed.keys())
+            .chain(self.arrays.keys()).chain(self.cubemaps.keys())
+            .filter_map(|name| self.info(name))
+            .collect::<Vec<_>>();
+        stats.sort_by(|a, b| b.bytes.cmp(&a.bytes));
+        stats
+    }
+
+    /// Estimated GPU memory taken by all loaded textures, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        self.textures.values()
+            .map(|texture| estimate_bytes(texture.dimensions(), texture.get_mipmap_levels()))
+            .sum::<usize>()
+            + self.compressed.iter()
+                .map(|(name, texture)| estimate_compressed_bytes(texture, self.formats.get(name).copied()))
+                .sum::<usize>()
+            + self.arrays.values()
+                .map(|array| estimate_bytes(array.dimensions(), array.get_mipmap_levels()) * array.array_size() as usize)
+                .sum::<usize>()
+            + self.cubemaps.values()
+                .map(|cubemap| {
+                    let size = cubemap.dimensions();
+                    estimate_bytes((size, size), cubemap.get_mipmap_levels()) * 6
+                })
+                .sum::<usize>()
+    }
+
+    /// Evicts least recently used textures until the memory usage fits `memory_budget`,
+    /// called by `update`. Only textures that were loaded from a file, haven't been used
+    /// during the last frame and are not referenced outside of the manager can be evicted.
+    pub fn enforce_budget(&mut self) {
+        let budget = match self.memory_budget {
+            Some(budget) => budget,
+            None => return
+        };
+        let mut usage = self.memory_usage();
+        if usage <= budget {
+            return;
+        }
+
+        let mut candidates = {
+            let last_used = self.last_used.borrow();
+            self.textures.iter()
+                .filter(|(name, texture)| self.sources.contains_key(*name) && Rc::strong_count(texture) == 1)
+                .map(|(name, _)| (last_used.get(name).cloned().unwrap_or(0), name.clone()))
+                .filter(|(frame, _)| *frame + 1 < self.frame)
+                .collect::<Vec<_>>()
+        };
+        candidates.sort();
+
+        for (_, name) in candidates {
+            if usage <= budget {
+                break;
+            }
+            if let Some(texture) = self.textures.remove(&name) {
+                usage -= estimate_bytes(texture.dimensions(), texture.get_mipmap_levels());
+                self.last_used.borrow_mut().remove(&name);
+                self.evicted.insert(name, texture.dimensions());
+            }
+        }
+    }
+}
+
+/// Cuts the faces out of a horizontal or vertical cross, in the order +X, -X, +Y, -Y, +Z, -Z.
+fn split_cross(image: &RgbaImage) -> Option<Vec<RgbaImage>> {
+    let (w, h) = image.dimensions();
+    let face = |column: u32, row: u32, size: u32| {
+        image::imageops::crop_imm(image, column * size, row * size, size, size).to_image()
+    };
+
+    if w * 3 == h * 4 {
+        let size = w / 4;
+        Some(vec![face(2, 1, size), face(0, 1, size), face(1, 0, size), face(1, 2, size), face(1, 1, size), face(3, 1, size)])
+    } else if w * 4 == h * 3 {
+        // -Z hangs below -Y upside down
+        let size = w / 3;
+        let back = image::imageops::rotate180(&face(1, 3, size));
+        Some(vec![face(2, 1, size), face(0, 1, size), face(1, 0, size), face(1, 2, size), face(1, 1, size), back])
+    } else {
+        None
+    }
+}
+
+/// RGBA8 storage of every mipmap level of a single layer.
+fn estimate_bytes((w, h): (u32, u32), mipmap_levels: u32) -> usize {
+    (0 .. mipmap_levels)
+        .map(|level| ((w >> level).max(1) * (h >> level).max(1)) as usize * 4)
+        .sum()
+}
+
+/// Every mipmap level in the block format, textures of unknown format are counted
+/// at the biggest block size.
+fn estimate_compressed_bytes(texture: &CompressedSrgbTexture2d, format: Option<BlockFormat>) -> usize {
+    let (w, h) = texture.dimensions();
+    let format = format.unwrap_or(BlockFormat::Bc3);
+    (0 .. texture.get_mipmap_levels())
+        .map(|level| format.level_bytes(w >> level, h >> level))
+        .sum()
+}
+
+/// Decodes an image file, block compressed containers are decompressed on the CPU.
+fn decode_file(path: &Path) -> Result<RgbaImage, TextureError> {
+    let bytes = std::fs::read(path)?;
+    if CompressedImage::is_container(&bytes) {
+        CompressedImage::parse(&bytes)?.decompress(0)
+    } else {
+        Ok(image::load_from_memory(&bytes)?.into_rgba8())
+    }
+}
+
+#[derive(Debug)]
+pub enum TextureError {
+    Io(std::io::Error),
+    Decode(image::ImageError),
+    Allocation(TextureCreationError),
+    Missing(String),
+    /// Array layers or cubemap faces that don't fit together.
+    Layout(String),
+    /// Pixels written outside of the texture.
+    OutOfBounds(String),
+    /// Unsupported or malformed compressed texture container.
+    Format(String),
+    /// Texture evicted by the memory budget that is being loaded again, see `try_get`.
+    Reloading(String),
+    /// Atlas region with the name of a region of another atlas.
+    DuplicateRegion(String),
+    /// Pixel data of a different length than its dimensions call for.
+    Size(String),
+    Framebuffer(ValidationError)
+}
+
+impl From<std::io::Error> for TextureError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<image::ImageError> for TextureError {
+    fn from(e: image::ImageError) -> Self {
+        match e {
+            image::ImageError::IoError(e) => Self::Io(e),
+            other => Self::Decode(other)
+        }
+    }
+}
+
+impl From<ValidationError> for TextureError {
+    fn from(e: ValidationError) -> Self {
+        Self::Framebuffer(e)
+    }
+}
+
+impl From<TextureCreationError> for TextureError {
+    fn from(e: TextureCreationError) -> Self {
+        Self::Allocation(e)
+    }
+}
@@ -28,7 +28,7 @@ pub fn create<T, S, C, H>(title: T, inner_size: S, depth_bits: u8, mut handler:
     let mut modifiers = ModifiersState::empty();
 
     let shaders = Rc::new(RefCell::new(ShaderManager::new(&display)));
-    let fonts = Rc::new(RefCell::new(FontManager::new(&display)));
+    let fonts = Rc::new(RefCell::new(FontManager::new(&display, shaders.clone())));
     let textures = Rc::new(RefCell::new(TextureManager::new(&display)));
 
     let mut last_frame = Instant::now();
@@ -91,6 +91,9 @@ pub fn create<T, S, C, H>(title: T, inner_size: S, depth_bits: u8, mut handler:
         let time_elapsed = now.duration_since(last_frame).as_secs_f32();
         last_frame = now;
 
+        textures.borrow_mut().update();
+        shaders.borrow_mut().reload_changed();
+
         let mut frame = display.draw();
 
         let mut canvas = Canvas::new(
@@ -98,6 +101,7 @@ pub fn create<T, S, C, H>(title: T, inner_size: S, depth_bits: u8, mut handler:
         );
 
         handler.draw_frame(&mut context, &mut canvas, time_elapsed);
+        canvas.shader_errors();
 
         canvas.into_inner().finish().expect("Frame finishing failed");
     });